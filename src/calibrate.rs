@@ -0,0 +1,365 @@
+use crate::config::Orientation;
+use crate::consts;
+use crate::decoder::Decoder;
+use crate::keyboard::transport;
+use crate::keyboard::{Configuration, Keyboard, WellKnownCode};
+use crate::mapping::{Device, Macropad};
+use crate::monitor::HidReader;
+
+use anyhow::{anyhow, ensure, Result};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use strum::IntoEnumIterator as _;
+
+/// Knob actions in the order they are numbered and calibrated
+const KNOB_ACTIONS: [&str; 3] = ["turn left", "press", "turn right"];
+
+/// Physical layout of a macropad worked out from the keys pressed in visual order
+#[derive(Debug)]
+pub struct Calibration {
+    /// device section matching the physical pad
+    pub device: Device,
+    /// knobs in visual order with their knob number, when the numbering differs
+    pub knob_order: Option<Vec<u8>>,
+}
+
+impl Calibration {
+    /// Returns the device section in ron format
+    pub fn device_ron(&self) -> String {
+        to_string_pretty(&self.device, PrettyConfig::new()).expect("Serialization failed")
+    }
+}
+
+/// Wizard finding the physical layout of a macropad. Every key and knob action is
+/// programmed with its own key, then the user presses them in visual order
+pub struct Calibrate {
+    /// rows of the pad as seen by the user
+    rows: u8,
+    /// columns of the pad as seen by the user
+    cols: u8,
+    /// number of knobs
+    knobs: u8,
+}
+
+impl Calibrate {
+    /// Returns the wizard for a pad
+    ///
+    /// #Arguments
+    /// `rows` - rows of the pad as seen by the user
+    /// `cols` - columns of the pad as seen by the user
+    /// `knobs` - number of knobs
+    ///
+    pub fn new(rows: u8, cols: u8, knobs: u8) -> Result<Self> {
+        let codes = usize::from(rows) * usize::from(cols) + 3 * usize::from(knobs);
+        ensure!(
+            codes <= Self::codes().len(),
+            "unable to calibrate more than {} keys and knob actions",
+            Self::codes().len()
+        );
+        Ok(Self { rows, cols, knobs })
+    }
+
+    /// Returns the rows, columns and knobs of the pad read from the device
+    ///
+    /// #Arguments
+    /// `keyboard` - opened device
+    ///
+    pub fn read_size(keyboard: &mut dyn Keyboard) -> Result<(u8, u8, u8)> {
+        let mut buf = vec![0; consts::READ_BUF_SIZE.into()];
+        keyboard.send(&keyboard.device_type())?;
+        ensure!(
+            keyboard.recieve(&mut buf)? > 0,
+            "unable to read the number of keys from the device"
+        );
+        let info = Decoder::get_device_info(&buf);
+        let (rows, cols) = keyboard.profile().geometry(info.num_keys)?;
+        Ok((rows, cols, info.num_encoders))
+    }
+
+    /// Returns the keys programmed to tell the keys of the pad apart
+    fn codes() -> Vec<String> {
+        // a-z then 1-0
+        WellKnownCode::iter()
+            .take(36)
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    /// Returns the configuration programming the key number n with the n-th code.
+    /// Knob actions follow the keys
+    ///
+    pub fn macropad(&self) -> Macropad {
+        let num_keys = self.rows * self.cols;
+        let mut macropad = Macropad::new(1, num_keys, self.knobs);
+        macropad.layers.truncate(1);
+        let mut codes = Self::codes().into_iter();
+        let layer = &mut macropad.layers[0];
+        for btn in layer.buttons[0].iter_mut() {
+            btn.mapping = codes.next().unwrap_or_default();
+        }
+        for knob in layer.knobs.iter_mut() {
+            for btn in [&mut knob.ccw, &mut knob.press, &mut knob.cw] {
+                btn.mapping = codes.next().unwrap_or_default();
+            }
+        }
+        macropad
+    }
+
+    /// Returns what the user is asked to press, in visual order
+    ///
+    pub fn prompts(&self) -> Vec<String> {
+        let mut prompts = Vec::new();
+        for row in 1..=self.rows {
+            for col in 1..=self.cols {
+                prompts.push(format!("press the key at row {row} column {col}"));
+            }
+        }
+        for knob in 1..=self.knobs {
+            for action in KNOB_ACTIONS {
+                prompts.push(format!(
+                    "{action} knob {knob} (knobs from top to bottom, left to right)"
+                ));
+            }
+        }
+        prompts
+    }
+
+    /// Returns the position of the code in the calibration configuration: the
+    /// key number for keys and num keys + knob action index for knob actions
+    ///
+    /// #Arguments
+    /// `event` - key press read from the pad
+    ///
+    fn position(&self, event: &str) -> Option<usize> {
+        let num_codes = usize::from(self.rows * self.cols) + 3 * usize::from(self.knobs);
+        Self::codes()
+            .iter()
+            .take(num_codes)
+            .position(|c| c == event)
+    }
+
+    /// Asks the user to press the keys and knob actions in visual order and
+    /// returns the position of each of them in the calibration configuration
+    ///
+    /// #Arguments
+    /// `reader` - reader of the key presses
+    ///
+    pub fn record(&self, reader: &mut HidReader) -> Result<Vec<usize>> {
+        let mut pressed = Vec::new();
+        for prompt in self.prompts() {
+            println!("{prompt}");
+            loop {
+                let Some((event, _)) = reader.next_event()? else {
+                    return Err(anyhow!("calibration interrupted"));
+                };
+                match self.position(&event) {
+                    Some(p) if !pressed.contains(&p) => {
+                        pressed.push(p);
+                        break;
+                    }
+                    // repeated reports of the previous key or knob
+                    Some(_) => (),
+                    None => println!("  {event} is not a calibration key, {prompt}"),
+                }
+            }
+        }
+        Ok(pressed)
+    }
+
+    /// Programs the configuration the device had before calibrating, so the
+    /// calibration keys are not left on it. Returns None without a previous
+    /// configuration
+    ///
+    /// #Arguments
+    /// `previous` - configuration of the device before calibrating
+    /// `program` - programs the device
+    ///
+    pub fn restore(
+        previous: Option<&Macropad>,
+        program: impl FnOnce(&Macropad) -> Result<()>,
+    ) -> Option<Result<()>> {
+        // a Ctrl-C ending the calibration must not stop the restore
+        transport::clear_interrupted();
+        previous.map(program)
+    }
+
+    /// Returns the layout matching the positions pressed in visual order
+    ///
+    /// #Arguments
+    /// `config` - driver numbering the keys
+    /// `pressed` - positions of the calibration configuration in visual order
+    ///
+    pub fn calibration(
+        &self,
+        config: &dyn Configuration,
+        pressed: &[usize],
+    ) -> Result<Calibration> {
+        let num_keys = usize::from(self.rows * self.cols);
+        ensure!(
+            pressed.len() == num_keys + 3 * usize::from(self.knobs),
+            "every key and knob action must be pressed once"
+        );
+        // key numbers are one based
+        let keys: Vec<u8> = pressed[..num_keys]
+            .iter()
+            .map(|p| u8::try_from(p + 1))
+            .collect::<Result<_, _>>()?;
+
+        let mut device = Device {
+            orientation: Orientation::Normal,
+            rows: self.rows,
+            cols: self.cols,
+            knobs: self.knobs,
+            led: None,
+            key_map: None,
+        };
+        let orientation = [
+            Orientation::Normal,
+            Orientation::UpsideDown,
+            Orientation::Clockwise,
+            Orientation::CounterClockwise,
+        ]
+        .into_iter()
+        .find(|o| {
+            config
+                .get_layout(*o, self.rows, self.cols)
+                .is_ok_and(|layout| layout.concat() == keys)
+        });
+        match orientation {
+            Some(orientation) => device.orientation = orientation,
+            None => {
+                device.key_map = Some(
+                    keys.chunks(usize::from(self.cols))
+                        .map(|row| row.to_vec())
+                        .collect(),
+                )
+            }
+        }
+        device.check_key_map()?;
+
+        // knobs in visual order, the actions of a knob must be turn left, press and
+        // turn right of the same knob
+        let knob_order: Vec<u8> = pressed[num_keys..]
+            .chunks(3)
+            .enumerate()
+            .map(|(k, actions)| {
+                let base = actions[0].checked_sub(num_keys).filter(|b| b % 3 == 0);
+                match base {
+                    Some(base) if actions == [0, 1, 2].map(|a| num_keys + base + a) => {
+                        Ok(u8::try_from(base / 3 + 1)?)
+                    }
+                    _ => Err(anyhow!(
+                        "knob {} was not turned left, pressed and turned right in this order, calibrate again",
+                        k + 1
+                    )),
+                }
+            })
+            .collect::<Result<_>>()?;
+        let in_order = knob_order.iter().copied().eq(1..=self.knobs);
+        Ok(Calibration {
+            device,
+            knob_order: (!in_order).then_some(knob_order),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calibrate::Calibrate;
+    use crate::config::Orientation;
+    use crate::consts;
+    use crate::keyboard::k8890::Keyboard8890;
+    use crate::keyboard::session::{Direction, Packet, Replay, Session};
+    use crate::keyboard::{new_keyboard, transport, Keyboard as _};
+    use crate::mapping::Macropad;
+    use crate::profile::Profiles;
+
+    #[test]
+    fn calibration_macropad() -> anyhow::Result<()> {
+        let calibrate = Calibrate::new(3, 4, 3)?;
+        let mp = calibrate.macropad();
+        assert_eq!((mp.device.rows, mp.device.cols), (1, 12));
+        assert_eq!(mp.layers[0].buttons[0][0].mapping, "a");
+        assert_eq!(mp.layers[0].buttons[0][11].mapping, "l");
+        assert_eq!(mp.layers[0].knobs[2].cw.mapping, "u");
+        assert_eq!(calibrate.prompts().len(), 21);
+        assert_eq!(calibrate.position("m"), Some(12));
+        assert_eq!(calibrate.position("v"), None);
+        assert!(Calibrate::new(6, 6, 1).is_err(), "too many keys");
+        Ok(())
+    }
+
+    #[test]
+    fn find_layout() -> anyhow::Result<()> {
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8842)?.clone();
+        let driver = new_keyboard(&profile, None)?;
+
+        // pad turned clockwise: 3 visual rows of 2 keys
+        let calibrate = Calibrate::new(3, 2, 1)?;
+        let layout = driver.get_layout(Orientation::Clockwise, 3, 2)?;
+        let mut pressed: Vec<usize> = layout
+            .concat()
+            .iter()
+            .map(|k| usize::from(*k) - 1)
+            .collect();
+        pressed.extend([6, 7, 8]);
+        let calibration = calibrate.calibration(driver.as_ref(), &pressed)?;
+        assert_eq!(calibration.device.orientation, Orientation::Clockwise);
+        assert_eq!(calibration.device.key_map, None);
+        assert_eq!(calibration.knob_order, None);
+
+        // no orientation numbers the keys like this, knobs bottom to top
+        let calibrate = Calibrate::new(2, 3, 2)?;
+        let pressed = [1, 0, 2, 3, 4, 5, 9, 10, 11, 6, 7, 8];
+        let calibration = calibrate.calibration(driver.as_ref(), &pressed)?;
+        assert_eq!(
+            calibration.device.key_map,
+            Some(vec![vec![2, 1, 3], vec![4, 5, 6]])
+        );
+        assert_eq!(calibration.knob_order, Some(vec![2, 1]));
+
+        // actions of different knobs or out of order
+        for knobs in [
+            [9, 7, 8, 6, 10, 11],
+            [9, 10, 8, 6, 7, 11],
+            [0, 7, 8, 9, 10, 11],
+        ] {
+            let mut pressed = vec![1, 0, 2, 3, 4, 5];
+            pressed.extend(knobs);
+            assert!(
+                calibrate.calibration(driver.as_ref(), &pressed).is_err(),
+                "{knobs:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn restore_after_interrupt() -> anyhow::Result<()> {
+        let _lock = transport::TEST_INTERRUPTS.lock();
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8890)?.clone();
+        let mut previous = Macropad::new(1, 3, 0);
+        previous.layers.truncate(1);
+        for btn in &mut previous.layers[0].buttons[0] {
+            btn.mapping = "a".to_string();
+        }
+        let msgs = Keyboard8890::new(None, profile.clone())?.program_msgs(&previous)?;
+        let mut session = Session::new(consts::VENDOR_ID, 0x8890);
+        for msg in &msgs {
+            session.packets.push(Packet {
+                time_us: 0,
+                direction: Direction::Out,
+                what: String::new(),
+                data: msg.clone(),
+            });
+        }
+        let mut kbd = Keyboard8890::new(Some(Box::new(Replay::new(session))), profile)?;
+
+        // Ctrl-C while waiting for the calibration keys
+        transport::interrupt();
+        let restored = Calibrate::restore(Some(&previous), |mp| kbd.program(mp));
+        assert!(matches!(restored, Some(Ok(()))), "{restored:?}");
+        assert!(!transport::interrupted());
+        assert!(Calibrate::restore(None, |_| unreachable!()).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,777 @@
+use crate::decoder::Decoder;
+use crate::keyboard::{LedColor, LedMode};
+use crate::mapping::{Layer, Led, Macropad};
+use crate::profile::{DeviceProfile, DriverFamily, Profiles};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use log::debug;
+use num::FromPrimitive;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Link type of linux usbmon captures
+const LINKTYPE_USB_LINUX: u32 = 189;
+/// Link type of linux usbmon captures with the memory mapped header
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+/// Link type of USBPcap captures (windows)
+const LINKTYPE_USBPCAP: u32 = 249;
+
+/// Type of USB transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferType {
+    Isochronous,
+    Interrupt,
+    Control,
+    Bulk,
+}
+
+impl TransferType {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Isochronous),
+            1 => Some(Self::Interrupt),
+            2 => Some(Self::Control),
+            3 => Some(Self::Bulk),
+            _ => None,
+        }
+    }
+}
+
+/// USB transfer carrying data, in the direction the data flows
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    /// Bus number
+    pub bus: u16,
+    /// Device address on the bus
+    pub device: u16,
+    /// Endpoint address, bit 7 is set for IN endpoints
+    pub endpoint: u8,
+    /// Type of transfer
+    pub transfer_type: TransferType,
+    /// Data of the transfer
+    pub data: Vec<u8>,
+}
+
+impl Transfer {
+    /// Returns true if the data was sent to the device
+    ///
+    pub fn is_out(&self) -> bool {
+        self.endpoint & 0x80 == 0
+    }
+}
+
+/// Configuration recovered from a capture
+pub struct Import {
+    /// Profile of the device that was programmed
+    pub profile: DeviceProfile,
+    /// Number of messages sent to the device
+    pub packets: usize,
+    /// Decoded configuration
+    pub macropad: Macropad,
+    /// Messages, bytes and fields that were not understood
+    pub notes: Vec<String>,
+}
+
+/// Key programmed by a capture
+struct DecodedKey {
+    layer: u8,
+    key_num: u8,
+    mapping: String,
+    delay: u16,
+}
+
+/// USB capture made with usbmon (linux) or USBPcap (windows), in pcap or pcapng format
+pub struct Capture {
+    /// Transfers of the capture, in the order they were captured
+    pub transfers: Vec<Transfer>,
+}
+
+impl Capture {
+    /// Reads the capture file
+    ///
+    /// #Arguments
+    /// `file` - pcap or pcapng file
+    ///
+    pub fn read(file: &Path) -> Result<Self> {
+        let data = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+        Self::parse(&data).with_context(|| format!("parsing {}", file.display()))
+    }
+
+    /// Returns the capture of the pcap or pcapng data
+    ///
+    /// #Arguments
+    /// `data` - content of the capture file
+    ///
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= 24, "file is too short to be a capture");
+        let packets = if data[..4] == [0x0a, 0x0d, 0x0d, 0x0a] {
+            Self::pcapng_packets(data)?
+        } else {
+            Self::pcap_packets(data)?
+        };
+
+        let mut transfers = Vec::new();
+        let mut skipped = 0;
+        for (linktype, packet) in packets {
+            match linktype {
+                LINKTYPE_USB_LINUX => transfers.extend(Self::usbmon_transfer(packet, 48)),
+                LINKTYPE_USB_LINUX_MMAPPED => transfers.extend(Self::usbmon_transfer(packet, 64)),
+                LINKTYPE_USBPCAP => transfers.extend(Self::usbpcap_transfer(packet)),
+                _ => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            debug!("skipped {skipped} packets that are not USB");
+        }
+        Ok(Self { transfers })
+    }
+
+    /// Returns the vendor/product id of the devices by bus/device address, for the
+    /// devices whose device descriptor is part of the capture
+    ///
+    pub fn devices(&self) -> BTreeMap<(u16, u16), (u16, u16)> {
+        self.transfers
+            .iter()
+            .filter(|t| t.transfer_type == TransferType::Control && !t.is_out())
+            .filter(|t| t.data.len() >= 12 && t.data[0] == 18 && t.data[1] == 1)
+            .map(|t| {
+                let vid = u16::from_le_bytes([t.data[8], t.data[9]]);
+                let pid = u16::from_le_bytes([t.data[10], t.data[11]]);
+                ((t.bus, t.device), (vid, pid))
+            })
+            .collect()
+    }
+
+    /// Decodes the configuration programmed into a device of the vendor
+    ///
+    /// #Arguments
+    /// `profiles` - device profiles
+    /// `vendor_id` - vendor id of the device
+    /// `product_id` - Optional product id, when the capture does not tell
+    /// `driver` - Optional driver family to decode the messages with
+    ///
+    pub fn import(
+        &self,
+        profiles: &Profiles,
+        vendor_id: u16,
+        product_id: Option<u16>,
+        driver: Option<DriverFamily>,
+    ) -> Result<Import> {
+        let mut notes = Vec::new();
+        let devices = self.devices();
+
+        // programming messages go to an interrupt OUT endpoint of the device
+        let candidates: Vec<&Transfer> = self
+            .transfers
+            .iter()
+            .filter(|t| t.is_out() && t.transfer_type == TransferType::Interrupt)
+            .filter(|t| Self::family(&t.data).is_some())
+            .filter(|t| match devices.get(&(t.bus, t.device)) {
+                Some((vid, _)) => *vid == vendor_id,
+                None => true,
+            })
+            .collect();
+        let Some(first) = candidates.first() else {
+            return Err(anyhow!(
+                "no programming messages for vendor id 0x{vendor_id:04x} in the capture"
+            ));
+        };
+        let address = (first.bus, first.device);
+        let msgs: Vec<&[u8]> = candidates
+            .iter()
+            .filter(|t| (t.bus, t.device) == address)
+            .map(|t| t.data.as_slice())
+            .collect();
+        if msgs.len() < candidates.len() {
+            notes.push(format!(
+                "capture has messages for several devices - only decoding bus {} device {}",
+                address.0, address.1
+            ));
+        }
+
+        let family = match driver {
+            Some(driver) => driver,
+            None => {
+                let k8890 = msgs
+                    .iter()
+                    .filter(|m| Self::family(m) == Some(DriverFamily::K8890))
+                    .count();
+                if k8890 * 2 > msgs.len() {
+                    DriverFamily::K8890
+                } else {
+                    DriverFamily::K884x
+                }
+            }
+        };
+        let product_id = product_id.or(devices.get(&address).map(|(_, pid)| *pid));
+        let profile = match product_id {
+            Some(pid) if profiles.find(vendor_id, pid).is_some() => {
+                profiles.resolve(vendor_id, pid, driver)?
+            }
+            Some(pid) => profiles.resolve(vendor_id, pid, Some(family))?,
+            None => {
+                notes.push(
+                    "device descriptor is not part of the capture - use -p to set the product id"
+                        .to_string(),
+                );
+                profiles.by_driver(family)?.clone()
+            }
+        };
+
+        let (keys, leds) = match profile.driver {
+            DriverFamily::K884x => Self::decode_884x(&msgs, &mut notes),
+            DriverFamily::K8890 => Self::decode_8890(&msgs, &mut notes),
+        };
+        let macropad = Self::macropad(&profile, keys, leds, &mut notes);
+        Ok(Import {
+            profile,
+            packets: msgs.len(),
+            macropad,
+            notes,
+        })
+    }
+
+    /// Returns the driver family whose programming messages look like `msg`
+    fn family(msg: &[u8]) -> Option<DriverFamily> {
+        if msg.len() < 4 || msg[0] != 0x03 {
+            return None;
+        }
+        match (msg[1], msg[2]) {
+            (0xfa | 0xfb | 0xfd | 0xfe, _) => Some(DriverFamily::K884x),
+            (0xa1 | 0xaa | 0xb0, _) => Some(DriverFamily::K8890),
+            (key_num, 0x11..=0x13) if key_num < 0x40 => Some(DriverFamily::K8890),
+            _ => None,
+        }
+    }
+
+    fn decode_884x(msgs: &[&[u8]], notes: &mut Vec<String>) -> (Vec<DecodedKey>, Vec<(u8, Led)>) {
+        let mut keys: Vec<DecodedKey> = Vec::new();
+        let mut leds = Vec::new();
+        for (i, msg) in msgs.iter().enumerate() {
+            let n = i + 1;
+            if msg.len() < 16 {
+                notes.push(format!("message #{n}: too short ({} bytes)", msg.len()));
+                continue;
+            }
+            match msg[1] {
+                0xfd if msg[2..4] == [0xfe, 0xff] => Self::check_unused(n, msg, 4, notes),
+                0xfd if msg[4] == 0x05 => {
+                    // delay of the key programmed by the previous message
+                    let delay = u16::from_le_bytes([msg[5], msg[6]]);
+                    match keys
+                        .iter_mut()
+                        .rev()
+                        .find(|k| (k.layer, k.key_num) == (msg[3], msg[2]))
+                    {
+                        Some(key) => key.delay = delay,
+                        None => notes.push(format!("message #{n}: delay for a key not programmed")),
+                    }
+                }
+                0xfd => match Decoder::get_programmed_key_mapping(msg) {
+                    Ok(km) => {
+                        let end = if msg[4] == 0x03 {
+                            16
+                        } else {
+                            11 + 2 * msg[10] as usize
+                        };
+                        Self::check_unused(n, msg, end, notes);
+                        keys.push(DecodedKey {
+                            layer: km.layer,
+                            key_num: km.key_number,
+                            mapping: km.keys.join(","),
+                            delay: 0,
+                        });
+                    }
+                    Err(e) => notes.push(format!("message #{n}: {e}")),
+                },
+                0xfe => {
+                    let color = LedColor::from_u8(msg[12] & 0xf0);
+                    if color.is_none() {
+                        notes.push(format!("message #{n}: unknown LED color 0x{:02x}", msg[12]));
+                    }
+                    leds.push((
+                        msg[3],
+                        Led {
+                            mode: LedMode::Index(msg[12] & 0x0f),
+                            color,
+                        },
+                    ));
+                    Self::check_unused(n, msg, 13, notes);
+                }
+                // reading the device type/configuration
+                0xfa | 0xfb => (),
+                _ => notes.push(format!("message #{n}: unknown message {:02x?}", &msg[..4])),
+            }
+        }
+        (keys, leds)
+    }
+
+    fn decode_8890(msgs: &[&[u8]], notes: &mut Vec<String>) -> (Vec<DecodedKey>, Vec<(u8, Led)>) {
+        let mut keys = Vec::new();
+        let mut leds = Vec::new();
+        let mut layer = 1;
+        // messages of the key being decoded, a key press takes one or two messages
+        let mut current: Vec<Vec<u8>> = Vec::new();
+        let mut flush = |current: &mut Vec<Vec<u8>>, layer: u8, notes: &mut Vec<String>| {
+            if let Some(first) = current.first() {
+                let key_num = first[1];
+                match Decoder::get_8890_mapping(current) {
+                    Ok(mapping) => keys.push(DecodedKey {
+                        layer,
+                        key_num,
+                        mapping,
+                        delay: 0,
+                    }),
+                    Err(e) => notes.push(format!("layer {layer} key {key_num}: {e}")),
+                }
+            }
+            current.clear();
+        };
+
+        for (i, msg) in msgs.iter().enumerate() {
+            let n = i + 1;
+            if msg.len() < 7 {
+                notes.push(format!("message #{n}: too short ({} bytes)", msg.len()));
+                continue;
+            }
+            match (msg[1], msg[2]) {
+                (0xa1, lyr) => {
+                    flush(&mut current, layer, notes);
+                    layer = lyr;
+                    Self::check_unused(n, msg, 3, notes);
+                }
+                (0xaa, _) => {
+                    flush(&mut current, layer, notes);
+                    Self::check_unused(n, msg, 3, notes);
+                }
+                (0xb0, 0x18) => {
+                    flush(&mut current, layer, notes);
+                    leds.push((
+                        layer,
+                        Led {
+                            mode: LedMode::Index(msg[3]),
+                            color: None,
+                        },
+                    ));
+                    Self::check_unused(n, msg, 4, notes);
+                }
+                (key_num, 0x11..=0x13) => {
+                    if current.first().is_some_and(|m| m[1] != key_num) {
+                        flush(&mut current, layer, notes);
+                    }
+                    current.push(msg.to_vec());
+                    Self::check_unused(n, msg, 7, notes);
+                }
+                _ => notes.push(format!("message #{n}: unknown message {:02x?}", &msg[..4])),
+            }
+        }
+        flush(&mut current, layer, notes);
+        (keys, leds)
+    }
+
+    /// Notes the non zero bytes from `end` on, which are not understood
+    fn check_unused(n: usize, msg: &[u8], end: usize, notes: &mut Vec<String>) {
+        let unused: Vec<String> = msg
+            .iter()
+            .enumerate()
+            .skip(end)
+            .filter(|(_, b)| **b != 0)
+            .map(|(i, b)| format!("{i}: 0x{b:02x}"))
+            .collect();
+        if !unused.is_empty() {
+            notes.push(format!("message #{n}: unknown bytes {}", unused.join(", ")));
+        }
+    }
+
+    /// Returns the configuration with the decoded keys and LEDs
+    fn macropad(
+        profile: &DeviceProfile,
+        keys: Vec<DecodedKey>,
+        leds: Vec<(u8, Led)>,
+        notes: &mut Vec<String>,
+    ) -> Macropad {
+        let knob_base = profile.knob_base;
+        let num_keys = keys
+            .iter()
+            .filter(|k| k.key_num < knob_base)
+            .map(|k| k.key_num)
+            .max()
+            .unwrap_or(0);
+        let num_knobs = keys
+            .iter()
+            .filter(|k| k.key_num >= knob_base)
+            .map(|k| (k.key_num - knob_base) / 3 + 1)
+            .max()
+            .unwrap_or(0);
+        let num_layers = keys
+            .iter()
+            .map(|k| k.layer)
+            .chain(leds.iter().map(|(layer, _)| *layer))
+            .max()
+            .unwrap_or(1)
+            .max(1);
+        let (rows, cols) = profile.geometry(num_keys).unwrap_or((1, num_keys));
+
+        let mut mp = Macropad::new(rows, cols, num_knobs);
+        mp.layers = (0..num_layers)
+            .map(|_| Layer::new(rows, cols, num_knobs))
+            .collect();
+        for key in keys {
+            if key.layer == 0 || key.key_num == 0 {
+                notes.push(format!(
+                    "layer {} key {}: invalid layer/key number",
+                    key.layer, key.key_num
+                ));
+                continue;
+            }
+            let layer = &mut mp.layers[(key.layer - 1) as usize];
+            let button = if key.key_num < knob_base {
+                let idx = (key.key_num - 1) as usize;
+                &mut layer.buttons[idx / cols as usize][idx % cols as usize]
+            } else {
+                let action = key.key_num - knob_base;
+                let knob = &mut layer.knobs[(action / 3) as usize];
+                match action % 3 {
+                    0 => &mut knob.ccw,
+                    1 => &mut knob.press,
+                    _ => &mut knob.cw,
+                }
+            };
+            button.mapping = key.mapping;
+            button.delay = key.delay;
+        }
+
+        for (layer, led) in leds {
+            if profile.led_per_layer && layer > 0 {
+                mp.layers[(layer - 1) as usize].led = Some(led);
+            } else {
+                mp.device.led = Some(led);
+            }
+        }
+        mp
+    }
+
+    fn pcap_packets(data: &[u8]) -> Result<Vec<(u32, &[u8])>> {
+        let big_endian = match data[..4] {
+            [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => false,
+            [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => true,
+            _ => return Err(anyhow!("not a pcap or pcapng file")),
+        };
+        let linktype = Self::u32_at(data, 20, big_endian)?;
+        let mut packets = Vec::new();
+        let mut off = 24;
+        while off + 16 <= data.len() {
+            let len = Self::u32_at(data, off + 8, big_endian)? as usize;
+            let packet = data
+                .get(off + 16..off + 16 + len)
+                .ok_or_else(|| anyhow!("truncated packet at offset {off}"))?;
+            packets.push((linktype, packet));
+            off += 16 + len;
+        }
+        Ok(packets)
+    }
+
+    fn pcapng_packets(data: &[u8]) -> Result<Vec<(u32, &[u8])>> {
+        let mut packets = Vec::new();
+        let mut linktypes: Vec<u32> = Vec::new();
+        let mut big_endian = false;
+        let mut off = 0;
+        while off + 12 <= data.len() {
+            if data[off..off + 4] == [0x0a, 0x0d, 0x0d, 0x0a] {
+                // section header, sets the byte order of the section
+                big_endian = data[off + 8..off + 12] == [0x1a, 0x2b, 0x3c, 0x4d];
+                linktypes.clear();
+            }
+            let block_type = Self::u32_at(data, off, big_endian)?;
+            let block_len = Self::u32_at(data, off + 4, big_endian)? as usize;
+            ensure!(
+                block_len >= 12 && off + block_len <= data.len(),
+                "invalid block at offset {off}"
+            );
+            let body = &data[off + 8..off + block_len - 4];
+            match block_type {
+                // interface description
+                1 => linktypes.push(Self::u16_at(body, 0, big_endian)? as u32),
+                // enhanced packet
+                6 => {
+                    let interface = Self::u32_at(body, 0, big_endian)? as usize;
+                    let len = Self::u32_at(body, 12, big_endian)? as usize;
+                    let packet = body
+                        .get(20..20 + len)
+                        .ok_or_else(|| anyhow!("truncated packet at offset {off}"))?;
+                    let linktype = linktypes
+                        .get(interface)
+                        .ok_or_else(|| anyhow!("unknown interface {interface}"))?;
+                    packets.push((*linktype, packet));
+                }
+                // simple packet
+                3 => {
+                    let len = (Self::u32_at(body, 0, big_endian)? as usize).min(body.len() - 4);
+                    let linktype = linktypes
+                        .first()
+                        .ok_or_else(|| anyhow!("packet before interface description"))?;
+                    packets.push((*linktype, &body[4..4 + len]));
+                }
+                _ => (),
+            }
+            off += block_len;
+        }
+        Ok(packets)
+    }
+
+    /// Returns the transfer of a usbmon packet, if it carries data in the
+    /// direction of the transfer
+    fn usbmon_transfer(packet: &[u8], header_len: usize) -> Option<Transfer> {
+        let header = packet.get(..header_len)?;
+        let event = header[8];
+        let endpoint = header[10];
+        let len = u32::from_le_bytes(header[36..40].try_into().ok()?) as usize;
+        let data = packet.get(header_len..)?;
+        let data = &data[..len.min(data.len())];
+        // data sent to the device is in the submission, data from it in the completion
+        let out = endpoint & 0x80 == 0;
+        if data.is_empty() || (out && event != b'S') || (!out && event != b'C') {
+            return None;
+        }
+        Some(Transfer {
+            bus: u16::from_le_bytes([header[12], header[13]]),
+            device: header[11] as u16,
+            endpoint,
+            transfer_type: TransferType::from_u8(header[9])?,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Returns the transfer of a USBPcap packet, if it carries data in the
+    /// direction of the transfer
+    fn usbpcap_transfer(packet: &[u8]) -> Option<Transfer> {
+        let header_len = u16::from_le_bytes([*packet.first()?, *packet.get(1)?]) as usize;
+        let header = packet.get(..header_len.max(27))?;
+        let from_device = header[16] & 0x01 == 0x01;
+        let endpoint = header[21];
+        let data = packet.get(header_len..)?;
+        let out = endpoint & 0x80 == 0;
+        if data.is_empty() || out == from_device {
+            return None;
+        }
+        Some(Transfer {
+            bus: u16::from_le_bytes([header[17], header[18]]),
+            device: u16::from_le_bytes([header[19], header[20]]),
+            endpoint,
+            transfer_type: TransferType::from_u8(header[22])?,
+            data: data.to_vec(),
+        })
+    }
+
+    fn u16_at(data: &[u8], off: usize, big_endian: bool) -> Result<u16> {
+        let bytes: [u8; 2] = data
+            .get(off..off + 2)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow!("unexpected end of file"))?;
+        Ok(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(data: &[u8], off: usize, big_endian: bool) -> Result<u32> {
+        let bytes: [u8; 4] = data
+            .get(off..off + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow!("unexpected end of file"))?;
+        Ok(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::Capture;
+    use crate::consts::VENDOR_ID;
+    use crate::keyboard::k884x::Keyboard884x;
+    use crate::keyboard::k8890::Keyboard8890;
+    use crate::keyboard::{LedColor, LedMode};
+    use crate::mapping::{Led, Macropad};
+    use crate::profile::Profiles;
+
+    /// USBPcap packet of a transfer
+    fn usbpcap(endpoint: u8, transfer: u8, from_device: bool, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![27, 0];
+        packet.extend_from_slice(&[0; 12]); // irp id, status
+        packet.extend_from_slice(&[0x09, 0x00]); // function
+        packet.push(from_device as u8);
+        packet.extend_from_slice(&1u16.to_le_bytes()); // bus
+        packet.extend_from_slice(&5u16.to_le_bytes()); // device
+        packet.push(endpoint);
+        packet.push(transfer);
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    /// pcapng file of the packets
+    fn pcapng(linktype: u16, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut block = |block_type: u32, body: &[u8]| {
+            let mut body = body.to_vec();
+            body.resize(body.len().div_ceil(4) * 4, 0);
+            let len = (body.len() + 12) as u32;
+            file.extend_from_slice(&block_type.to_le_bytes());
+            file.extend_from_slice(&len.to_le_bytes());
+            file.extend_from_slice(&body);
+            file.extend_from_slice(&len.to_le_bytes());
+        };
+        let mut shb = 0x1a2b3c4du32.to_le_bytes().to_vec();
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        block(0x0a0d0d0a, &shb);
+        let mut idb = linktype.to_le_bytes().to_vec();
+        idb.extend_from_slice(&[0, 0, 0, 0, 1, 0]);
+        block(1, &idb);
+        for packet in packets {
+            let mut epb = vec![0; 12];
+            epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            epb.extend_from_slice(packet);
+            block(6, &epb);
+        }
+        file
+    }
+
+    fn device_descriptor(pid: u16) -> Vec<u8> {
+        let mut desc = vec![18, 1, 0x10, 0x01, 0, 0, 0, 8];
+        desc.extend_from_slice(&VENDOR_ID.to_le_bytes());
+        desc.extend_from_slice(&pid.to_le_bytes());
+        desc.extend_from_slice(&[0, 1, 1, 2, 0, 1]);
+        desc
+    }
+
+    #[test]
+    fn import_884x() -> anyhow::Result<()> {
+        let profiles = Profiles::builtin();
+        let profile = profiles.get(VENDOR_ID, 0x8842)?.clone();
+        let mut mp = Macropad::new(2, 3, 1);
+        mp.layers.truncate(2);
+        for (i, mapping) in [
+            "ctrl-a,b",
+            "volumeup",
+            "click",
+            "ctrl-wheelup",
+            "shift-x",
+            "y",
+        ]
+        .iter()
+        .enumerate()
+        {
+            mp.layers[0].buttons[i / 3][i % 3].mapping = mapping.to_string();
+            mp.layers[1].buttons[i / 3][i % 3].mapping = "z".to_string();
+        }
+        mp.layers[0].buttons[0][0].delay = 100;
+        for layer in &mut mp.layers {
+            layer.knobs[0].ccw.mapping = "volumedown".to_string();
+            layer.knobs[0].press.mapping = "mute".to_string();
+            layer.knobs[0].cw.mapping = "volumeup".to_string();
+        }
+        mp.layers[1].led = Some(Led {
+            mode: LedMode::Index(1),
+            color: Some(LedColor::Blue),
+        });
+        let msgs = Keyboard884x::new(None, profile)?.program_msgs(&mp)?;
+
+        let mut packets = vec![usbpcap(0x80, 2, true, &device_descriptor(0x8842))];
+        packets.extend(msgs.iter().map(|m| usbpcap(0x02, 1, false, m)));
+        // the answers of the device are not programming messages
+        packets.push(usbpcap(0x81, 1, true, &[0x03, 0xfb, 0x06, 0x01]));
+        let capture = Capture::parse(&pcapng(249, &packets))?;
+        let import = capture.import(&profiles, VENDOR_ID, None, None)?;
+
+        assert_eq!(import.profile.product_id, 0x8842);
+        assert_eq!(import.packets, msgs.len());
+        assert!(import.notes.is_empty(), "{:?}", import.notes);
+        let decoded = &import.macropad;
+        assert_eq!((decoded.device.rows, decoded.device.cols), (2, 3));
+        assert_eq!(decoded.layers.len(), 2);
+        for (layer, expected) in decoded.layers.iter().zip(&mp.layers) {
+            for (row, expected_row) in layer.buttons.iter().zip(&expected.buttons) {
+                for (btn, expected_btn) in row.iter().zip(expected_row) {
+                    assert_eq!(btn.mapping, expected_btn.mapping);
+                    assert_eq!(btn.delay, expected_btn.delay);
+                }
+            }
+            assert_eq!(layer.knobs[0].press.mapping, "mute");
+            assert_eq!(layer.knobs[0].cw.mapping, "volumeup");
+            assert_eq!(layer.led, expected.led);
+        }
+
+        // truncated messages and unknown media keys are reported, not decoded
+        let mut media = msgs.iter().find(|m| m[4] == 0x02).unwrap().clone();
+        media[11] = 0;
+        packets.push(usbpcap(0x02, 1, false, &media));
+        packets.push(usbpcap(0x02, 1, false, &msgs[0][..20]));
+        let capture = Capture::parse(&pcapng(249, &packets))?;
+        let import = capture.import(&profiles, VENDOR_ID, None, None)?;
+        assert_eq!(import.notes.len(), 2, "{:?}", import.notes);
+        assert!(import.notes[0].contains("unknown media key"));
+        assert!(import.notes[1].contains("too short"));
+        Ok(())
+    }
+
+    #[test]
+    fn import_8890() -> anyhow::Result<()> {
+        let profiles = Profiles::builtin();
+        let profile = profiles.get(VENDOR_ID, 0x8890)?.clone();
+        let mut mp = Macropad::new(1, 3, 1);
+        mp.layers.truncate(1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-a,b".to_string();
+        mp.layers[0].buttons[0][1].mapping = "volumeup".to_string();
+        mp.layers[0].buttons[0][2].mapping = "click".to_string();
+        mp.layers[0].knobs[0].ccw.mapping = "wheeldown".to_string();
+        mp.layers[0].knobs[0].press.mapping = "shift-x".to_string();
+        mp.layers[0].knobs[0].cw.mapping = "wheelup".to_string();
+        mp.device.led = Some(Led {
+            mode: LedMode::Index(2),
+            color: None,
+        });
+        let mut msgs = Keyboard8890::new(None, profile)?.program_msgs(&mp)?;
+        msgs[3][40] = 0x55;
+
+        // usbmon capture without the device descriptor
+        let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&220u32.to_le_bytes());
+        for msg in &msgs {
+            let mut packet = vec![0; 64];
+            packet[8] = b'S';
+            packet[9] = 1;
+            packet[10] = 0x01;
+            packet[11] = 7;
+            packet[36..40].copy_from_slice(&(msg.len() as u32).to_le_bytes());
+            packet.extend_from_slice(msg);
+            file.extend_from_slice(&[0; 8]);
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&packet);
+        }
+        let capture = Capture::parse(&file)?;
+        let import = capture.import(&profiles, VENDOR_ID, None, None)?;
+
+        assert_eq!(import.profile.product_id, 0x8890);
+        assert_eq!(import.notes.len(), 2, "{:?}", import.notes);
+        assert!(import.notes[0].contains("-p"));
+        assert!(import.notes[1].contains("#4") && import.notes[1].contains("40: 0x55"));
+        let layer = &import.macropad.layers[0];
+        assert_eq!(layer.buttons[0][0].mapping, "ctrl-a,b");
+        assert_eq!(layer.buttons[0][1].mapping, "volumeup");
+        assert_eq!(layer.buttons[0][2].mapping, "click");
+        assert_eq!(layer.knobs[0].ccw.mapping, "wheeldown");
+        assert_eq!(layer.knobs[0].press.mapping, "shift-x");
+        assert_eq!(layer.knobs[0].cw.mapping, "wheelup");
+        assert_eq!(import.macropad.device.led, mp.device.led);
+        Ok(())
+    }
+}
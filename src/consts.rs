@@ -1,31 +1,33 @@
 use std::time::Duration;
 
-// Supported devices listed by vendor/product IDs
+// Default vendor ID of the supported devices. Products are listed in the device profiles
 pub const VENDOR_ID: u16 = 0x1189;
-pub const PRODUCT_IDS: [u16; 3] = [0x8840, 0x8842, 0x8890];
 
 /// Timeout for reading from USB
 ///
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
 
-/// Number of layers on the macropad. Depending on the model,
-/// some layers are no accessible
+/// Number of times a failed USB transfer is retried
 ///
-pub const NUM_LAYERS: u8 = 3;
+pub const DEFAULT_RETRIES: u8 = 3;
 
-/// Read buffer size (in bytes)
+/// Time given to the device to come back before claiming it again
 ///
-pub const READ_BUF_SIZE: u8 = 72;
+pub const RECLAIM_DELAY: Duration = Duration::from_millis(500);
+
+/// Maximum number of layers of a configuration when validating without
+/// a device profile
+///
+pub const NUM_LAYERS: u8 = 3;
 
-/// Maximum number of key presses that can be assigned to a key
-/// for a 0x884X macropad
+/// Maximum number of key presses of a key when validating without a device
+/// profile (the most any supported device can be programmed with)
 ///
-pub const MAX_KEY_PRESSES_884X: usize = 17;
+pub const MAX_KEY_PRESSES: usize = 17;
 
-/// Maximum number of key presses that can be assigned to a key
-/// for a 0x8890 macropad
+/// Read buffer size (in bytes)
 ///
-pub const MAX_KEY_PRESSES_8890: usize = 5;
+pub const READ_BUF_SIZE: u8 = 72;
 
 /// Maximum delay for a keypress
 ///
@@ -0,0 +1,290 @@
+use crate::mapping::{Button, Knob, Layer, Macropad};
+
+use anyhow::{ensure, Result};
+
+/// How buttons that do not fit the new grid are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Strategy {
+    /// Keep buttons at their row and column, drop the ones outside the new grid
+    Truncate,
+    /// Fill the new grid row by row with the buttons in row-major order, drop the rest
+    Reflow,
+    /// Reflow, moving the buttons and knobs that do not fit to additional layers
+    Layers,
+}
+
+/// Configuration converted to another geometry
+pub struct Conversion {
+    /// converted configuration
+    pub macropad: Macropad,
+    /// bindings that did not fit, eg. "layer 1 row 2 button 3 (ctrl-a)"
+    pub dropped: Vec<String>,
+    /// number of layers added for the bindings that did not fit
+    pub added_layers: usize,
+}
+
+/// Binding with its place in the configuration being converted
+enum Binding {
+    Button(String, Button),
+    Knob(String, Knob),
+}
+
+impl Binding {
+    /// Returns the place and mapping of the binding for the report
+    fn describe(&self) -> String {
+        match self {
+            Binding::Button(place, btn) => format!("{place} ({})", btn.mapping),
+            Binding::Knob(place, knob) => format!(
+                "{place} ({} / {} / {})",
+                knob.ccw.mapping, knob.press.mapping, knob.cw.mapping
+            ),
+        }
+    }
+
+    /// Returns true if nothing is mapped
+    fn is_empty(&self) -> bool {
+        match self {
+            Binding::Button(_, btn) => btn.mapping.is_empty(),
+            Binding::Knob(_, knob) => [&knob.ccw, &knob.press, &knob.cw]
+                .iter()
+                .all(|b| b.mapping.is_empty()),
+        }
+    }
+}
+
+/// Conversion of configurations between device geometries
+pub struct Convert {}
+
+impl Convert {
+    /// Returns the configuration remapped to the specified number of rows, columns
+    /// and knobs
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to convert
+    /// `geometry` - rows, columns and knobs of the target device
+    /// `strategy` - how buttons that do not fit are handled
+    /// `max_layers` - number of layers of the target device
+    ///
+    pub fn convert(
+        macropad: Macropad,
+        geometry: (u8, u8, u8),
+        strategy: Strategy,
+        max_layers: u8,
+    ) -> Result<Conversion> {
+        let (rows, cols, num_knobs) = geometry;
+        ensure!(rows > 0 && cols > 0, "the target device has no buttons");
+        let mut device = macropad.device;
+        if device.key_map.take().is_some() {
+            eprintln!(
+                "Warning - the key map does not apply to the new geometry, run calibrate again"
+            );
+        }
+        (device.rows, device.cols, device.knobs) = (rows, cols, num_knobs);
+
+        let mut layers = Vec::new();
+        let mut overflow = Vec::new();
+        let mut dropped = Vec::new();
+        for (l, layer) in macropad.layers.into_iter().enumerate() {
+            let mut new_layer = Layer::new(rows, cols, num_knobs);
+            new_layer.led = layer.led;
+            let mut lost = Vec::new();
+
+            let buttons =
+                layer.buttons.into_iter().enumerate().flat_map(|(r, row)| {
+                    row.into_iter().enumerate().map(move |(c, btn)| (r, c, btn))
+                });
+            match strategy {
+                Strategy::Truncate => {
+                    for (r, c, btn) in buttons {
+                        match new_layer.buttons.get_mut(r).and_then(|row| row.get_mut(c)) {
+                            Some(slot) => *slot = btn,
+                            None => lost.push(Self::button(l, r, c, btn)),
+                        }
+                    }
+                }
+                Strategy::Reflow | Strategy::Layers => {
+                    let mut buttons = buttons;
+                    let slots = new_layer.buttons.iter_mut().flatten();
+                    for (slot, (_, _, btn)) in slots.zip(buttons.by_ref()) {
+                        *slot = btn;
+                    }
+                    lost.extend(buttons.map(|(r, c, btn)| Self::button(l, r, c, btn)));
+                }
+            }
+
+            let mut knobs = layer.knobs.into_iter().enumerate();
+            for (slot, (_, knob)) in new_layer.knobs.iter_mut().zip(knobs.by_ref()) {
+                *slot = knob;
+            }
+            lost.extend(
+                knobs.map(|(k, knob)| {
+                    Binding::Knob(format!("layer {} knob {}", l + 1, k + 1), knob)
+                }),
+            );
+
+            layers.push(new_layer);
+            let lost = lost.into_iter().filter(|b| !b.is_empty());
+            match strategy {
+                Strategy::Layers => overflow.extend(lost),
+                _ => dropped.extend(lost),
+            }
+        }
+
+        // bindings that did not fit go to additional layers, as long as there are
+        // layers and places left for them
+        let num_layers = layers.len();
+        let (mut buttons, mut knobs): (Vec<_>, Vec<_>) = overflow
+            .into_iter()
+            .partition(|b| matches!(b, Binding::Button(..)));
+        if num_knobs == 0 {
+            dropped.append(&mut knobs);
+        }
+        buttons.reverse();
+        knobs.reverse();
+        while (!buttons.is_empty() || !knobs.is_empty()) && layers.len() < max_layers.into() {
+            let mut layer = Layer::new(rows, cols, num_knobs);
+            for slot in layer.buttons.iter_mut().flatten() {
+                match buttons.pop() {
+                    Some(Binding::Button(_, btn)) => *slot = btn,
+                    _ => break,
+                }
+            }
+            for slot in layer.knobs.iter_mut() {
+                match knobs.pop() {
+                    Some(Binding::Knob(_, knob)) => *slot = knob,
+                    _ => break,
+                }
+            }
+            layers.push(layer);
+        }
+        dropped.extend(buttons.into_iter().rev());
+        dropped.extend(knobs.into_iter().rev());
+
+        // layers the target device does not have
+        let kept = layers.len().min(max_layers.into());
+        for (l, layer) in layers.drain(kept..).enumerate() {
+            let l = l + usize::from(max_layers);
+            for (r, row) in layer.buttons.into_iter().enumerate() {
+                for (c, btn) in row.into_iter().enumerate() {
+                    dropped.push(Self::button(l, r, c, btn));
+                }
+            }
+            for (k, knob) in layer.knobs.into_iter().enumerate() {
+                dropped.push(Binding::Knob(
+                    format!("layer {} knob {}", l + 1, k + 1),
+                    knob,
+                ));
+            }
+        }
+        let added_layers = layers.len().saturating_sub(num_layers);
+
+        Ok(Conversion {
+            macropad: Macropad { device, layers },
+            dropped: dropped
+                .iter()
+                .filter(|b| !b.is_empty())
+                .map(Binding::describe)
+                .collect(),
+            added_layers,
+        })
+    }
+
+    /// Returns the button with its place in the configuration being converted
+    fn button(layer: usize, row: usize, col: usize, btn: Button) -> Binding {
+        Binding::Button(
+            format!("layer {} row {} button {}", layer + 1, row + 1, col + 1),
+            btn,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::{Convert, Strategy};
+    use crate::mapping::Macropad;
+
+    /// 2x3 pad with 2 knobs on 2 layers, buttons mapped to a-f and g-l
+    fn macropad() -> Macropad {
+        let mut mp = Macropad::new(2, 3, 2);
+        mp.layers.truncate(2);
+        let mut keys = ('a'..='z').map(|c| c.to_string());
+        for layer in mp.layers.iter_mut() {
+            for btn in layer.buttons.iter_mut().flatten() {
+                btn.mapping = keys.next().unwrap();
+            }
+        }
+        mp.layers[0].knobs[1].press.mapping = "mute".to_string();
+        mp
+    }
+
+    fn mappings(mp: &Macropad, layer: usize) -> Vec<Vec<&str>> {
+        mp.layers[layer]
+            .buttons
+            .iter()
+            .map(|row| row.iter().map(|b| b.mapping.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn truncate() -> anyhow::Result<()> {
+        let conversion = Convert::convert(macropad(), (3, 2, 1), Strategy::Truncate, 3)?;
+        let mp = &conversion.macropad;
+        assert_eq!((mp.device.rows, mp.device.cols, mp.device.knobs), (3, 2, 1));
+        assert_eq!(mappings(mp, 0), [["a", "b"], ["d", "e"], ["", ""]]);
+        assert_eq!(
+            conversion.dropped,
+            [
+                "layer 1 row 1 button 3 (c)",
+                "layer 1 row 2 button 3 (f)",
+                "layer 1 knob 2 ( / mute / )",
+                "layer 2 row 1 button 3 (i)",
+                "layer 2 row 2 button 3 (l)",
+            ]
+        );
+        assert_eq!(conversion.added_layers, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn reflow() -> anyhow::Result<()> {
+        let conversion = Convert::convert(macropad(), (2, 2, 2), Strategy::Reflow, 3)?;
+        let mp = &conversion.macropad;
+        assert_eq!(mappings(mp, 0), [["a", "b"], ["c", "d"]]);
+        assert_eq!(mappings(mp, 1), [["g", "h"], ["i", "j"]]);
+        assert_eq!(mp.layers[0].knobs[1].press.mapping, "mute");
+        assert_eq!(conversion.dropped.len(), 4, "{:?}", conversion.dropped);
+
+        // a bigger grid has empty buttons at the end
+        let conversion = Convert::convert(macropad(), (2, 4, 2), Strategy::Reflow, 3)?;
+        assert_eq!(
+            mappings(&conversion.macropad, 0),
+            [["a", "b", "c", "d"], ["e", "f", "", ""]]
+        );
+        assert!(conversion.dropped.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn overflow_layers() -> anyhow::Result<()> {
+        let conversion = Convert::convert(macropad(), (2, 2, 1), Strategy::Layers, 3)?;
+        let mp = &conversion.macropad;
+        assert_eq!(mp.layers.len(), 3);
+        assert_eq!(conversion.added_layers, 1);
+        assert_eq!(mappings(mp, 2), [["e", "f"], ["k", "l"]]);
+        assert_eq!(mp.layers[2].knobs[0].press.mapping, "mute");
+        assert!(conversion.dropped.is_empty());
+
+        // no layer left for the overflow
+        let conversion = Convert::convert(macropad(), (1, 2, 1), Strategy::Layers, 3)?;
+        assert_eq!(conversion.macropad.layers.len(), 3);
+        assert_eq!(mappings(&conversion.macropad, 2), [["c", "d"]]);
+        assert_eq!(conversion.dropped[0], "layer 1 row 2 button 2 (e)");
+        assert_eq!(conversion.dropped.len(), 6, "{:?}", conversion.dropped);
+
+        let conversion = Convert::convert(macropad(), (2, 3, 2), Strategy::Layers, 1)?;
+        assert_eq!(conversion.macropad.layers.len(), 1);
+        assert_eq!(conversion.dropped.len(), 6);
+        assert!(Convert::convert(macropad(), (0, 3, 0), Strategy::Reflow, 3).is_err());
+        Ok(())
+    }
+}
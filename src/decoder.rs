@@ -1,7 +1,11 @@
-use crate::keyboard::{MediaCode, WellKnownCode};
+use crate::consts;
+use crate::keyboard::{
+    MediaCode, MouseAction, MouseButton, MouseButtons, MouseEvent, MouseModifier, WellKnownCode,
+};
 use anyhow::{anyhow, Result};
 use log::debug;
 use num::FromPrimitive;
+use strum::IntoEnumIterator as _;
 
 pub struct Decoder {}
 
@@ -48,7 +52,32 @@ impl Decoder {
                 "Message does not appear to be a response from device"
             ));
         }
+        Self::decode_key_mapping(buf)
+    }
 
+    /// Returns the key mapping of a 0x884x programming message. Its layout
+    /// matches the response when reading the configuration
+    ///
+    /// #Arguments
+    /// `buf` - programming message
+    ///
+    pub fn get_programmed_key_mapping(buf: &[u8]) -> Result<KeyMapping> {
+        if buf.len() < 2 || buf[1] != 0xfd {
+            return Err(anyhow!(
+                "Message does not appear to be a programming message"
+            ));
+        }
+        if buf.len() < consts::PACKET_SIZE {
+            return Err(anyhow!(
+                "programming message too short ({} bytes, expected {})",
+                buf.len(),
+                consts::PACKET_SIZE
+            ));
+        }
+        Self::decode_key_mapping(buf)
+    }
+
+    fn decode_key_mapping(buf: &[u8]) -> Result<KeyMapping> {
         let mut key_press: Vec<String> = Vec::new();
         let mut i = 11;
 
@@ -59,49 +88,14 @@ impl Decoder {
 
         // can we do this or should we check if bit 0 and bit 1 is set?
         if buf[4] == 0x03 {
-            // mouse wheel
-            let mut wheel_mapping = String::new();
-            if buf[10] == 0x04 {
-                let mod_key = Self::get_key(&[buf[11], buf[12]]);
-                if let Some(kc) = mod_key {
-                    wheel_mapping = Self::modifier_to_str(kc.modifier);
-                }
-            } else if buf[10] == 0x01 {
-            }
-
-            // mouse click
-            let mut click_type = wheel_mapping;
-            if !click_type.is_empty() {
-                click_type += "-";
-            }
-            match buf[12] {
-                0x01 => click_type = "click".to_string(),
-                0x02 => click_type = "rclick".to_string(),
-                0x04 => click_type = "mclick".to_string(),
-                _ => (),
-            }
-
-            // mouse wheel status
-            if click_type.ends_with('-') {
-                click_type.pop();
-            }
-            let mut key_str = click_type;
-            match buf[15] {
-                0x01 => {
-                    if !key_str.is_empty() {
-                        key_str += "-";
-                    }
-                    key_str += "wheelup";
-                }
-                0xFF => {
-                    if !key_str.is_empty() {
-                        key_str += "-";
-                    }
-                    key_str += "wheeldown";
-                }
-                _ => (),
-            }
-            key_press.push(key_str);
+            // buttons in byte 12 and wheel in byte 15, programming messages always
+            // carry the modifier in byte 11
+            let modifier = if buf[10] == 0x04 || buf[1] == 0xfd {
+                buf[11]
+            } else {
+                0
+            };
+            key_press.push(Self::mouse_event(modifier, buf[12], buf[15])?);
 
             // TODO: is it possible to make a binding like wheelup-a? doesn't make much sense
             //       but might need to add support for that. currently, not supported
@@ -119,10 +113,8 @@ impl Decoder {
             let mut tmp = vec![0u8, 2];
             tmp[1] = buf[i];
 
-            let val = Self::get_key(&tmp);
-
-            let result = val.unwrap();
-            //println!("result: {:?}", result);
+            let result =
+                Self::get_key(&tmp).ok_or_else(|| anyhow!("unknown media key 0x{:02x}", buf[i]))?;
             let mut key_str = Self::modifier_to_str(result.modifier);
             if let Some(mc) = result.media_code {
                 if !key_str.is_empty() {
@@ -165,6 +157,73 @@ impl Decoder {
         })
     }
 
+    /// Returns the mapping of a key from the 0x8890 messages programming it. Each
+    /// key press has its own message, key presses with keyboard keys are preceded
+    /// by a message with the number of key presses
+    ///
+    /// #Arguments
+    /// `msgs` - messages programming the key, in the order they were sent
+    ///
+    pub fn get_8890_mapping(msgs: &[Vec<u8>]) -> Result<String> {
+        let mut key_press: Vec<String> = Vec::new();
+        let mut count = None;
+        for msg in msgs {
+            if msg.len() < 7 || msg[0] != 0x03 {
+                return Err(anyhow!("Message does not appear to program a key"));
+            }
+            if msg[1] != msgs[0][1] {
+                return Err(anyhow!(
+                    "messages program keys 0x{:02x} and 0x{:02x}",
+                    msgs[0][1],
+                    msg[1]
+                ));
+            }
+            match msg[2] {
+                // keyboard keys, number of key presses when msg[4] is 0
+                0x11 => {
+                    if msg[4] == 0 {
+                        count = Some(usize::from(msg[3]));
+                        continue;
+                    }
+                    if usize::from(msg[4]) != key_press.len() + 1 {
+                        return Err(anyhow!(
+                            "key press {} is sent as key press {}",
+                            key_press.len() + 1,
+                            msg[4]
+                        ));
+                    }
+                    let mut key_str = Self::modifier_to_str(msg[5]);
+                    if msg[6] > 0 {
+                        let wkc = <WellKnownCode as FromPrimitive>::from_u8(msg[6])
+                            .ok_or_else(|| anyhow!("unknown key code 0x{:02x}", msg[6]))?;
+                        if !key_str.is_empty() {
+                            key_str += "-";
+                        }
+                        key_str += &wkc.to_string();
+                    }
+                    key_press.push(key_str);
+                }
+                // media keys, high byte of the code in msg[4]
+                0x12 => {
+                    let value = u16::from_le_bytes([msg[3], msg[4]]);
+                    let mc = <MediaCode as FromPrimitive>::from_u16(value)
+                        .ok_or_else(|| anyhow!("unknown media code 0x{value:04x}"))?;
+                    key_press.push(mc.to_string());
+                }
+                // mouse buttons in msg[3], modifier in msg[5], wheel in msg[6]
+                0x13 => key_press.push(Self::mouse_event(msg[5], msg[3], msg[6])?),
+                t => return Err(anyhow!("unknown key type 0x{t:02x}")),
+            }
+        }
+        if let Some(count) = count.filter(|c| *c != key_press.len()) {
+            return Err(anyhow!(
+                "{count} key presses announced but {} sent",
+                key_press.len()
+            ));
+        }
+        Ok(key_press.join(","))
+    }
+
     fn get_key(buf: &[u8]) -> Option<KeyCode> {
         let val = u16::from_be_bytes([buf[0], buf[1]]);
         debug!("val: 0x{val:02x}");
@@ -187,6 +246,46 @@ impl Decoder {
         })
     }
 
+    /// Returns the mapping of a mouse key press
+    ///
+    /// #Arguments
+    /// `modifier` - modifier bits
+    /// `buttons` - mouse button bits
+    /// `wheel` - 0x01 for wheel up and 0xff for wheel down
+    ///
+    fn mouse_event(modifier: u8, buttons: u8, wheel: u8) -> Result<String> {
+        let buttons: MouseButtons = MouseButton::iter()
+            .filter(|b| buttons >> (*b as u8) & 1 == 1)
+            .collect();
+        let wheel = match wheel {
+            0x00 => None,
+            0x01 => Some(MouseAction::WheelUp),
+            0xff => Some(MouseAction::WheelDown),
+            w => return Err(anyhow!("unknown mouse wheel value 0x{w:02x}")),
+        };
+        let action = match (buttons.is_empty(), wheel) {
+            (false, None) => MouseAction::Click(buttons),
+            (true, Some(wheel)) => wheel,
+            (true, None) => return Err(anyhow!("mouse key press without buttons or wheel")),
+            // not expressible as a mouse event, show what is sent
+            (false, Some(wheel)) => {
+                let mut mapping = Self::modifier_to_str(modifier);
+                if !mapping.is_empty() {
+                    mapping += "-";
+                }
+                return Ok(format!("{mapping}{}-{wheel}", MouseAction::Click(buttons)));
+            }
+        };
+        let modifier = match modifier {
+            0 => None,
+            m => Some(
+                <MouseModifier as FromPrimitive>::from_u8(m)
+                    .ok_or_else(|| anyhow!("unknown mouse modifier 0x{m:02x}"))?,
+            ),
+        };
+        Ok(MouseEvent(action, modifier).to_string())
+    }
+
     pub fn modifier_to_str(modifier: u8) -> String {
         let mut retval = Vec::new();
         for i in 0..=7 {
@@ -0,0 +1,424 @@
+use crate::options::Options;
+use crate::profile::{DeviceProfile, Profiles};
+use crate::watch::Watch;
+
+use anyhow::{anyhow, Result};
+use rusb::{Context, Device, DeviceDescriptor, UsbContext as _};
+use std::fmt;
+
+/// Directories udev reads rules from
+const UDEV_RULES_DIRS: [&str; 4] = [
+    "/etc/udev/rules.d",
+    "/run/udev/rules.d",
+    "/usr/lib/udev/rules.d",
+    "/lib/udev/rules.d",
+];
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "[ok]  "),
+            Status::Warn => write!(f, "[warn]"),
+            Status::Fail => write!(f, "[FAIL]"),
+        }
+    }
+}
+
+/// Check of the checklist
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// outcome of the check
+    pub status: Status,
+    /// what was checked
+    pub what: String,
+    /// how to fix a failed check
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn pass(what: impl Into<String>) -> Self {
+        Self {
+            status: Status::Pass,
+            what: what.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(what: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warn,
+            what: what.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(what: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: Status::Fail,
+            what: what.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "  {} {}", self.status, self.what)?;
+        if let Some(fix) = &self.fix {
+            write!(f, "\n         fix: {fix}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Diagnoses why the devices cannot be accessed or programmed
+pub struct Doctor {}
+
+impl Doctor {
+    /// Runs the checks for every device of the vendor, prints the checklist and
+    /// returns an error when a check failed
+    ///
+    /// #Arguments
+    /// `options` - command line options
+    /// `profiles` - device profiles
+    ///
+    pub fn run(options: &Options, profiles: &Profiles) -> Result<()> {
+        let vendor_id = options.devel_options.vendor_id;
+        let usb_options = vec![
+            #[cfg(windows)]
+            rusb::UsbOption::use_usbdk(),
+        ];
+        let context = match Context::with_options(&usb_options) {
+            Ok(context) => context,
+            Err(e) => {
+                let check = Check::fail(
+                    format!("initializing libusb - {e}"),
+                    "make sure libusb is installed and USB is available (containers need /dev/bus/usb)",
+                );
+                println!("{check}");
+                return Err(anyhow!("1 check failed"));
+            }
+        };
+
+        let mut failed = 0;
+        let mut found = 0;
+        for device in context.devices()?.iter() {
+            let Ok(desc) = device.device_descriptor() else {
+                continue;
+            };
+            if desc.vendor_id() != vendor_id {
+                continue;
+            }
+            if options
+                .devel_options
+                .product_id
+                .is_some_and(|pid| pid != desc.product_id())
+            {
+                continue;
+            }
+            found += 1;
+            println!(
+                "Bus {:03} Device {:03} (port {}): 0x{:04x}:0x{:04x}",
+                device.bus_number(),
+                device.address(),
+                Watch::port_path(&device),
+                desc.vendor_id(),
+                desc.product_id()
+            );
+            let checks = Self::check_device(options, profiles, &device, &desc);
+            for check in &checks {
+                println!("{check}");
+            }
+            failed += checks.iter().filter(|c| c.status == Status::Fail).count();
+            println!();
+        }
+
+        if found == 0 {
+            let check = Check::fail(
+                format!("no device with vendor id 0x{vendor_id:04x} is connected"),
+                "plug the macropad in (try another cable/port), `lsusb` should list it",
+            );
+            println!("{check}");
+            return Err(anyhow!("1 check failed"));
+        }
+        match failed {
+            0 => {
+                println!("no problems found");
+                Ok(())
+            }
+            1 => Err(anyhow!("1 check failed")),
+            n => Err(anyhow!("{n} checks failed")),
+        }
+    }
+
+    /// Returns the checks of the device, stopping at the first check the
+    /// following checks depend on
+    ///
+    /// #Arguments
+    /// `options` - command line options
+    /// `profiles` - device profiles
+    /// `device` - usb device
+    /// `desc` - device descriptor of the device
+    ///
+    fn check_device(
+        options: &Options,
+        profiles: &Profiles,
+        device: &Device<Context>,
+        desc: &DeviceDescriptor,
+    ) -> Vec<Check> {
+        let mut checks = Vec::new();
+        let (vid, pid) = (desc.vendor_id(), desc.product_id());
+        let driver = options.devel_options.driver;
+
+        // device profile
+        let profile = match (profiles.find(vid, pid), driver) {
+            (Some(profile), _) => {
+                checks.push(Check::pass(format!("device profile {}", profile.name)));
+                profile.clone()
+            }
+            (None, Some(driver)) => match profiles.resolve(vid, pid, Some(driver)) {
+                Ok(profile) => {
+                    checks.push(Check::warn(
+                        format!("no device profile, using {}", profile.name),
+                        "add a device profile once the device works",
+                    ));
+                    profile
+                }
+                Err(e) => {
+                    checks.push(Check::fail(format!("{e:#}"), "use another --driver"));
+                    return checks;
+                }
+            },
+            (None, None) => {
+                checks.push(Check::fail(
+                    format!("no device profile for 0x{pid:04x}"),
+                    "add a device profile (see Device profiles in the README) or try --driver",
+                ));
+                return checks;
+            }
+        };
+
+        // permissions
+        if cfg!(target_os = "linux") {
+            checks.push(Self::check_node(device));
+            checks.push(Self::check_udev_rules(&profile));
+        }
+
+        // interface and endpoints
+        let interface = match crate::find_interface_and_endpoint(
+            device,
+            options.devel_options.interface_number,
+            options.devel_options.out_endpoint_address,
+            options.devel_options.in_endpoint_address,
+        ) {
+            Ok((interface, out_endpoint, in_endpoint)) => {
+                checks.push(Check::pass(format!(
+                    "interface {interface} has 64 byte interrupt endpoints OUT 0x{out_endpoint:02x} IN 0x{in_endpoint:02x}"
+                )));
+                if in_endpoint == 0xff {
+                    checks.push(Check::warn(
+                        "no IN endpoint, the device cannot answer",
+                        "the configuration cannot be read back, programming may still work",
+                    ));
+                }
+                interface
+            }
+            Err(e) => {
+                checks.push(Check::fail(
+                    format!("interface/endpoints - {e:#}"),
+                    "set --interface-number / --out-endpoint-address for this device",
+                ));
+                return checks;
+            }
+        };
+
+        // open and kernel driver
+        match device.open() {
+            Ok(handle) => {
+                checks.push(Check::pass("device can be opened"));
+                match handle.kernel_driver_active(interface) {
+                    Ok(true) => checks.push(Check::pass(format!(
+                        "kernel driver bound to interface {interface}, it is detached while the tool runs"
+                    ))),
+                    Ok(false) => checks.push(Check::pass(format!(
+                        "no kernel driver bound to interface {interface}"
+                    ))),
+                    Err(rusb::Error::NotSupported) => (),
+                    Err(e) => checks.push(Check::warn(
+                        format!("unable to tell if a kernel driver is bound - {e}"),
+                        "run with RUST_LOG=debug for details",
+                    )),
+                }
+            }
+            Err(e) => {
+                checks.push(Check::fail(
+                    format!("opening the device - {e}"),
+                    Self::access_fix(),
+                ));
+                return checks;
+            }
+        }
+
+        // claim and probe
+        let mut keyboard = match crate::open_device(options, profiles, device, desc, pid) {
+            Ok(keyboard) => {
+                checks.push(Check::pass(format!("interface {interface} can be claimed")));
+                keyboard
+            }
+            Err(e) => {
+                checks.push(Check::fail(
+                    format!("claiming the interface - {e:#}"),
+                    "close other programs using the macropad (vendor software, other instances of the tool)",
+                ));
+                return checks;
+            }
+        };
+        if !profile.read_config {
+            checks.push(Check::pass(format!(
+                "{} does not answer the device type probe (not expected)",
+                profile.name
+            )));
+            return checks;
+        }
+        let mut buf = vec![0; crate::consts::READ_BUF_SIZE.into()];
+        let answer = keyboard
+            .send(&keyboard.device_type())
+            .and_then(|_| keyboard.recieve(&mut buf));
+        match answer {
+            Ok(n) if n > 0 => {
+                let info = crate::decoder::Decoder::get_device_info(&buf);
+                checks.push(Check::pass(format!(
+                    "device type probe answered: {} keys, {} knobs",
+                    info.num_keys, info.num_encoders
+                )));
+            }
+            // some 0x8840 clones cannot be read back but are programmed fine
+            Ok(_) => checks.push(Check::warn(
+                "device type probe was not answered - programming still works, reading the device back does not",
+                "try a longer --timeout, or another --driver if the device is not in the list",
+            )),
+            Err(e) => checks.push(Check::fail(
+                format!("device type probe - {e:#}"),
+                "try another USB port/cable, or --timeout / --retries",
+            )),
+        }
+        checks
+    }
+
+    /// Returns the check that the device node can be opened for reading and writing
+    ///
+    /// #Arguments
+    /// `device` - usb device
+    ///
+    fn check_node(device: &Device<Context>) -> Check {
+        let node = format!(
+            "/dev/bus/usb/{:03}/{:03}",
+            device.bus_number(),
+            device.address()
+        );
+        match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&node)
+        {
+            Ok(_) => Check::pass(format!("device node {node} is readable and writable")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Check::warn(
+                format!("device node {node} not found"),
+                "usbfs is not mounted at /dev/bus/usb, check the container/sandbox setup",
+            ),
+            Err(e) => Check::fail(format!("device node {node} - {e}"), Self::access_fix()),
+        }
+    }
+
+    /// Returns the check that there are udev rules for the device
+    ///
+    /// #Arguments
+    /// `profile` - profile of the device
+    ///
+    fn check_udev_rules(profile: &DeviceProfile) -> Check {
+        let files = UDEV_RULES_DIRS
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|e| e.ok().map(|e| e.path()));
+        for file in files {
+            let Ok(rules) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            if Self::rules_cover(&rules, profile.vendor_id, profile.product_id) {
+                return Check::pass(format!("udev rules in {}", file.display()));
+            }
+        }
+        Check::warn(
+            format!(
+                "no udev rules for 0x{:04x}:0x{:04x}",
+                profile.vendor_id, profile.product_id
+            ),
+            Self::access_fix(),
+        )
+    }
+
+    /// Returns true if a rule of the udev rules file matches the vendor/product id
+    ///
+    /// #Arguments
+    /// `rules` - content of a udev rules file
+    /// `vid` - vendor id
+    /// `pid` - product id
+    ///
+    fn rules_cover(rules: &str, vid: u16, pid: u16) -> bool {
+        let vendor = format!("{{idvendor}}==\"{vid:04x}\"");
+        let product = format!("{{idproduct}}==\"{pid:04x}\"");
+        rules.lines().any(|line| {
+            let line = line.trim().to_ascii_lowercase();
+            !line.starts_with('#') && line.contains(&vendor) && line.contains(&product)
+        })
+    }
+
+    fn access_fix() -> String {
+        if cfg!(target_os = "linux") {
+            "sudo macropad-tool udev-rules --install && sudo udevadm control --reload && sudo udevadm trigger, then plug the macropad in again".to_string()
+        } else {
+            "run the tool as Administrator".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doctor::{Check, Doctor};
+
+    #[test]
+    fn udev_rules() {
+        let rules = include_str!("../80-macropad.rules");
+        assert!(Doctor::rules_cover(rules, 0x1189, 0x8890));
+        assert!(!Doctor::rules_cover(rules, 0x1189, 0x1234));
+        assert!(!Doctor::rules_cover(
+            "# ATTRS{idProduct}==\"8890\", ATTRS{idVendor}==\"1189\"",
+            0x1189,
+            0x8890
+        ));
+        assert!(Doctor::rules_cover(
+            "SUBSYSTEM==\"usb\", ATTR{idVendor}==\"1189\", ATTR{idProduct}==\"88A0\", TAG+=\"uaccess\"",
+            0x1189,
+            0x88a0
+        ));
+    }
+
+    #[test]
+    fn checklist() {
+        let check = Check::fail("opening the device", "run as root");
+        assert_eq!(
+            check.to_string(),
+            "  [FAIL] opening the device\n         fix: run as root"
+        );
+        assert_eq!(Check::pass("ok").to_string(), "  [ok]   ok");
+    }
+}
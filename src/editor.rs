@@ -0,0 +1,720 @@
+use crate::keyboard::{MediaCode, Modifier, MouseAction, MouseButton, WellKnownCode};
+use crate::mapping::{Button, Macropad, Mapping};
+use crate::profile::DeviceProfile;
+
+use anyhow::Result;
+use log::debug;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize as _};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Clear, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use strum::EnumMessage as _;
+use strum::IntoEnumIterator as _;
+
+/// Callback used to program the device with the edited configuration
+pub type ProgramFn<'a> = Box<dyn FnMut(&Macropad) -> Result<()> + 'a>;
+
+/// Field of a binding that is being edited as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Mapping,
+    Label,
+    Description,
+    Delay,
+}
+
+/// What the keyboard input currently applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    /// Moving around the layers and keys
+    Navigate,
+    /// Typing the value of a field of the selected binding
+    Edit(Field, String),
+    /// Picking a key from the list of supported keys
+    Picker { filter: String, selected: usize },
+}
+
+/// Entry of the key picker
+struct PickerEntry {
+    /// Kind of key (modifier, key, media, mouse)
+    kind: &'static str,
+    /// Name of the key as used in a mapping
+    name: String,
+}
+
+/// Terminal UI for editing a Macropad
+pub struct Editor<'a> {
+    macropad: Macropad,
+    cfg_file: String,
+    profile: Option<DeviceProfile>,
+    layer: usize,
+    row: usize,
+    col: usize,
+    mode: Mode,
+    status: String,
+    /// warnings about settings the device ignores, or why the configuration is invalid
+    validation: Result<Vec<String>, String>,
+    modified: bool,
+    /// whether the device was programmed, which may have printed to the terminal
+    repaint: bool,
+    quit: bool,
+    program: Option<ProgramFn<'a>>,
+    entries: Vec<PickerEntry>,
+}
+
+impl<'a> Editor<'a> {
+    /// Creates a new editor for the specified configuration
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to edit
+    /// `cfg_file` - file the configuration is saved to
+    /// `profile` - Optional device profile to validate against
+    /// `program` - Optional callback used to program the device
+    ///
+    pub fn new(
+        macropad: Macropad,
+        cfg_file: &str,
+        profile: Option<DeviceProfile>,
+        program: Option<ProgramFn<'a>>,
+    ) -> Self {
+        let mut editor = Self {
+            macropad,
+            cfg_file: cfg_file.to_string(),
+            profile,
+            layer: 0,
+            row: 0,
+            col: 0,
+            mode: Mode::Navigate,
+            status: String::new(),
+            validation: Ok(Vec::new()),
+            modified: false,
+            repaint: false,
+            quit: false,
+            program,
+            entries: Self::picker_entries(),
+        };
+        editor.validate();
+        editor
+    }
+
+    /// Runs the editor until the user quits
+    ///
+    pub fn run(&mut self) -> Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                    if std::mem::take(&mut self.repaint) {
+                        // programming may print to the terminal, so repaint everything
+                        terminal.clear()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        debug!("key: {key:?} mode: {:?}", self.mode);
+        match self.mode.clone() {
+            Mode::Navigate => self.navigate(key.code),
+            Mode::Edit(field, text) => self.edit(field, text, key.code),
+            Mode::Picker { filter, selected } => self.pick(filter, selected, key.code),
+        }
+    }
+
+    fn navigate(&mut self, code: KeyCode) {
+        let quit_requested = self.status.starts_with("unsaved changes");
+        self.status.clear();
+        match code {
+            KeyCode::Up => self.move_to(self.row.saturating_sub(1), self.col),
+            KeyCode::Down => self.move_to(self.row + 1, self.col),
+            KeyCode::Left => self.move_to(self.row, self.col.saturating_sub(1)),
+            KeyCode::Right => self.move_to(self.row, self.col + 1),
+            KeyCode::Tab | KeyCode::PageDown => {
+                self.layer = (self.layer + 1) % self.macropad.layers.len();
+                self.move_to(self.row, self.col);
+            }
+            KeyCode::BackTab | KeyCode::PageUp => {
+                self.layer =
+                    (self.layer + self.macropad.layers.len() - 1) % self.macropad.layers.len();
+                self.move_to(self.row, self.col);
+            }
+            KeyCode::Enter | KeyCode::Char('e') => self.start_edit(Field::Mapping),
+            KeyCode::Char('l') => self.start_edit(Field::Label),
+            KeyCode::Char('i') => self.start_edit(Field::Description),
+            KeyCode::Char('d') => self.start_edit(Field::Delay),
+            KeyCode::Char('a') => {
+                self.mode = Mode::Picker {
+                    filter: String::new(),
+                    selected: 0,
+                }
+            }
+            KeyCode::Char('x') | KeyCode::Delete => {
+                if let Some(btn) = self.selected_mut() {
+                    btn.mapping.clear();
+                }
+                self.changed();
+            }
+            KeyCode::Char('s') => self.save(),
+            KeyCode::Char('w') => self.program(),
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.modified && !quit_requested {
+                    self.status = "unsaved changes - press q again to quit".to_string();
+                } else {
+                    self.quit = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn edit(&mut self, field: Field, mut text: String, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.mode = Mode::Navigate,
+            KeyCode::Enter => {
+                self.mode = Mode::Navigate;
+                let Some(btn) = self.selected_mut() else {
+                    return;
+                };
+                match field {
+                    Field::Mapping => btn.mapping = text.trim().to_string(),
+                    Field::Label => btn.label = text,
+                    Field::Description => btn.description = text,
+                    Field::Delay => match text.trim().parse::<u16>() {
+                        Ok(delay) => btn.delay = delay,
+                        Err(e) => {
+                            self.status = format!("invalid delay '{text}' - {e}");
+                            return;
+                        }
+                    },
+                }
+                self.changed();
+            }
+            KeyCode::Backspace => {
+                text.pop();
+                self.mode = Mode::Edit(field, text);
+            }
+            KeyCode::Char(c) => {
+                text.push(c);
+                self.mode = Mode::Edit(field, text);
+            }
+            _ => (),
+        }
+    }
+
+    fn pick(&mut self, mut filter: String, mut selected: usize, code: KeyCode) {
+        match code {
+            KeyCode::Esc => {
+                self.mode = Mode::Navigate;
+                return;
+            }
+            KeyCode::Enter => {
+                self.mode = Mode::Navigate;
+                let choice = self
+                    .filtered_entries(&filter)
+                    .get(selected)
+                    .map(|e| (e.kind, e.name.clone()));
+                if let (Some((kind, name)), Some(btn)) = (choice, self.selected_mut()) {
+                    Self::insert_key(&mut btn.mapping, &name, kind == "modifier");
+                    self.changed();
+                }
+                return;
+            }
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected += 1,
+            KeyCode::Backspace => {
+                filter.pop();
+                selected = 0;
+            }
+            KeyCode::Char(c) => {
+                filter.push(c);
+                selected = 0;
+            }
+            _ => (),
+        }
+        let count = self.filtered_entries(&filter).len();
+        selected = selected.min(count.saturating_sub(1));
+        self.mode = Mode::Picker { filter, selected };
+    }
+
+    /// Appends `key` to the mapping, starting a new key press in the sequence
+    /// unless the mapping ends with a modifier
+    fn insert_key(mapping: &mut String, key: &str, modifier: bool) {
+        if !mapping.is_empty() && !mapping.ends_with('-') {
+            mapping.push(',');
+        }
+        mapping.push_str(key);
+        if modifier {
+            mapping.push('-');
+        }
+    }
+
+    fn start_edit(&mut self, field: Field) {
+        let Some(btn) = self.selected() else {
+            return;
+        };
+        let text = match field {
+            Field::Mapping => btn.mapping.clone(),
+            Field::Label => btn.label.clone(),
+            Field::Description => btn.description.clone(),
+            Field::Delay => btn.delay.to_string(),
+        };
+        self.mode = Mode::Edit(field, text);
+    }
+
+    fn changed(&mut self) {
+        self.modified = true;
+        self.validate();
+    }
+
+    fn validate(&mut self) {
+        self.validation = Mapping::validate_config(&self.macropad, self.profile.as_ref())
+            .map_err(|e| e.to_string());
+    }
+
+    fn save(&mut self) {
+        // comments of the file are kept
+        match Mapping::update(&self.macropad, &self.cfg_file) {
+            Ok(()) => {
+                self.modified = false;
+                self.status = format!("saved {}", self.cfg_file);
+            }
+            Err(e) => self.status = format!("unable to save {} - {e:#}", self.cfg_file),
+        }
+    }
+
+    fn program(&mut self) {
+        if let Err(e) = &self.validation {
+            self.status = format!("not programming an invalid configuration - {e}");
+            return;
+        }
+        let Some(program) = self.program.as_mut() else {
+            self.status = "programming is not available".to_string();
+            return;
+        };
+        self.repaint = true;
+        self.status = match program(&self.macropad) {
+            Ok(()) => "successfully programmed device".to_string(),
+            Err(e) => format!("programming failed - {e:#}"),
+        };
+    }
+
+    /// Number of navigable rows on the current layer: button rows, then one row per knob
+    fn num_rows(&self) -> usize {
+        let layer = &self.macropad.layers[self.layer];
+        layer.buttons.len() + layer.knobs.len()
+    }
+
+    fn num_cols(&self, row: usize) -> usize {
+        let layer = &self.macropad.layers[self.layer];
+        match layer.buttons.get(row) {
+            Some(r) => r.len(),
+            None => 3,
+        }
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        let rows = self.num_rows();
+        if rows == 0 {
+            return;
+        }
+        self.row = row.min(rows - 1);
+        self.col = col.min(self.num_cols(self.row).saturating_sub(1));
+    }
+
+    fn button(&self, row: usize, col: usize) -> Option<&Button> {
+        let layer = self.macropad.layers.get(self.layer)?;
+        match layer.buttons.get(row) {
+            Some(r) => r.get(col),
+            None => {
+                let knob = layer.knobs.get(row - layer.buttons.len())?;
+                [&knob.ccw, &knob.press, &knob.cw].get(col).copied()
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<&Button> {
+        self.button(self.row, self.col)
+    }
+
+    fn selected_mut(&mut self) -> Option<&mut Button> {
+        let layer = self.macropad.layers.get_mut(self.layer)?;
+        let rows = layer.buttons.len();
+        match layer.buttons.get_mut(self.row) {
+            Some(r) => r.get_mut(self.col),
+            None => {
+                let knob = layer.knobs.get_mut(self.row - rows)?;
+                match self.col {
+                    0 => Some(&mut knob.ccw),
+                    1 => Some(&mut knob.press),
+                    2 => Some(&mut knob.cw),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn picker_entries() -> Vec<PickerEntry> {
+        let mut entries = Vec::new();
+        for m in Modifier::iter() {
+            entries.push(PickerEntry {
+                kind: "modifier",
+                name: m.get_serializations()[0].to_string(),
+            });
+        }
+        for c in WellKnownCode::iter() {
+            entries.push(PickerEntry {
+                kind: "key",
+                name: c.to_string(),
+            });
+        }
+        for c in MediaCode::iter() {
+            entries.push(PickerEntry {
+                kind: "media",
+                name: c.to_string(),
+            });
+        }
+        for b in MouseButton::iter() {
+            entries.push(PickerEntry {
+                kind: "mouse",
+                name: b.to_string(),
+            });
+        }
+        for a in [MouseAction::WheelUp, MouseAction::WheelDown] {
+            entries.push(PickerEntry {
+                kind: "mouse",
+                name: a.to_string(),
+            });
+        }
+        entries
+    }
+
+    fn filtered_entries(&self, filter: &str) -> Vec<&PickerEntry> {
+        let filter = filter.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|e| e.name.contains(&filter) || e.kind.starts_with(&filter))
+            .collect()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, grid, details, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(4),
+            Constraint::Length(6),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        let device = self
+            .profile
+            .as_ref()
+            .map(|p| p.name.as_str())
+            .unwrap_or("any device");
+        let title = format!(
+            " {}{} - layer {}/{} - {device}",
+            self.cfg_file,
+            if self.modified { " [modified]" } else { "" },
+            self.layer + 1,
+            self.macropad.layers.len()
+        );
+        frame.render_widget(Line::from(title).bold(), header);
+
+        self.draw_grid(frame, grid);
+        self.draw_details(frame, details);
+
+        let help = "arrows move  tab layer  enter/e edit  a add key  x clear  l label  \
+                    i description  d delay  s save  w program  q quit";
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(self.status.clone()).yellow(),
+                Line::from(help).dark_gray(),
+            ]),
+            footer,
+        );
+
+        match &self.mode {
+            Mode::Navigate => (),
+            Mode::Edit(field, text) => {
+                let area = Self::popup(frame.area(), 60, 3);
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(format!("{text}_"))
+                        .block(Block::bordered().title(format!(" {field:?} (enter/esc) "))),
+                    area,
+                );
+            }
+            Mode::Picker { filter, selected } => {
+                let area = Self::popup(frame.area(), 40, 20);
+                frame.render_widget(Clear, area);
+                let items: Vec<String> = self
+                    .filtered_entries(filter)
+                    .iter()
+                    .map(|e| format!("{:<22} {}", e.name, e.kind))
+                    .collect();
+                let mut state = ListState::default().with_selected(Some(*selected));
+                frame.render_stateful_widget(
+                    List::new(items)
+                        .block(Block::bordered().title(format!(" add key: {filter}_ ")))
+                        .highlight_style(Style::new().reversed()),
+                    area,
+                    &mut state,
+                );
+            }
+        }
+    }
+
+    fn draw_grid(&self, frame: &mut Frame, area: Rect) {
+        let rows = Layout::vertical(vec![Constraint::Length(4); self.num_rows()]).split(area);
+        let layer = &self.macropad.layers[self.layer];
+        for (row_idx, row_area) in rows.iter().enumerate() {
+            let cols = self.num_cols(row_idx);
+            let cells =
+                Layout::horizontal(vec![Constraint::Ratio(1, cols as u32); cols]).split(*row_area);
+            for (col_idx, cell) in cells.iter().enumerate() {
+                let Some(btn) = self.button(row_idx, col_idx) else {
+                    continue;
+                };
+                let title = if row_idx < layer.buttons.len() {
+                    format!(" {},{} ", row_idx + 1, col_idx + 1)
+                } else {
+                    let action = ["ccw", "press", "cw"][col_idx];
+                    format!(" knob {} {action} ", row_idx - layer.buttons.len() + 1)
+                };
+                let mut block = Block::bordered().title(title);
+                if row_idx == self.row && col_idx == self.col {
+                    block = block.border_style(Style::new().yellow().bold());
+                }
+                let mut lines = vec![Line::from(Mapping::pretty(&btn.mapping))];
+                if !btn.label.is_empty() {
+                    lines.insert(0, Line::from(btn.label.clone()).bold());
+                }
+                frame.render_widget(Paragraph::new(lines).block(block), *cell);
+            }
+        }
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = Vec::new();
+        if let Some(btn) = self.selected() {
+            lines.push(Line::from(format!("mapping:     {}", btn.mapping)));
+            lines.push(Line::from(format!("delay:       {}", btn.delay)));
+            lines.push(Line::from(format!("label:       {}", btn.label)));
+            lines.push(Line::from(format!("description: {}", btn.description)));
+        }
+        match &self.validation {
+            Ok(warnings) => {
+                lines.push(Line::from("config is valid").fg(Color::Green));
+                lines.extend(
+                    warnings
+                        .iter()
+                        .map(|w| Line::from(format!("warning: {w}")).fg(Color::Yellow)),
+                );
+            }
+            Err(e) => lines.push(Line::from(format!("invalid: {e}")).fg(Color::Red)),
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" selected ")),
+            area,
+        );
+    }
+
+    fn popup(area: Rect, width: u16, height: u16) -> Rect {
+        let width = width.min(area.width);
+        let height = height.min(area.height);
+        Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::VENDOR_ID;
+    use crate::editor::{Editor, Mode};
+    use crate::mapping::Macropad;
+    use crate::profile::{DeviceProfile, Profiles};
+    use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+    fn press(editor: &mut Editor, codes: &[KeyCode]) {
+        for code in codes {
+            editor.handle_key(KeyEvent::from(*code));
+        }
+    }
+
+    fn type_text(editor: &mut Editor, text: &str) {
+        for c in text.chars() {
+            editor.handle_key(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    fn profile(pid: u16) -> Option<DeviceProfile> {
+        Profiles::builtin().find(VENDOR_ID, pid).cloned()
+    }
+
+    fn macropad() -> Macropad {
+        let mut mp = Macropad::new(2, 3, 1);
+        for layer in mp.layers.iter_mut() {
+            for btn in layer.buttons.iter_mut().flatten() {
+                btn.mapping = "a".to_string();
+            }
+            for knob in layer.knobs.iter_mut() {
+                for btn in [&mut knob.ccw, &mut knob.press, &mut knob.cw] {
+                    btn.mapping = "b".to_string();
+                }
+            }
+        }
+        mp
+    }
+
+    #[test]
+    fn navigate() {
+        let mut editor = Editor::new(macropad(), "test.ron", None, None);
+        press(
+            &mut editor,
+            &[KeyCode::Right, KeyCode::Right, KeyCode::Right],
+        );
+        assert_eq!((editor.row, editor.col), (0, 2));
+        // knob row has three actions
+        press(&mut editor, &[KeyCode::Down, KeyCode::Down, KeyCode::Down]);
+        assert_eq!((editor.row, editor.col), (2, 2));
+        press(&mut editor, &[KeyCode::Tab]);
+        assert_eq!(editor.layer, 1);
+        press(&mut editor, &[KeyCode::BackTab, KeyCode::BackTab]);
+        assert_eq!(editor.layer, 2);
+    }
+
+    #[test]
+    fn edit_fields() {
+        let mut editor = Editor::new(macropad(), "test.ron", profile(0x8840), None);
+        press(
+            &mut editor,
+            &[KeyCode::Right, KeyCode::Enter, KeyCode::Backspace],
+        );
+        type_text(&mut editor, "ctrl-c");
+        press(&mut editor, &[KeyCode::Enter, KeyCode::Char('l')]);
+        type_text(&mut editor, "Copy");
+        press(&mut editor, &[KeyCode::Enter, KeyCode::Char('d')]);
+        press(&mut editor, &[KeyCode::Backspace]);
+        type_text(&mut editor, "250");
+        press(&mut editor, &[KeyCode::Enter]);
+
+        let btn = &editor.macropad.layers[0].buttons[0][1];
+        assert_eq!(btn.mapping, "ctrl-c");
+        assert_eq!(btn.label, "Copy");
+        assert_eq!(btn.delay, 250);
+        assert!(editor.modified);
+        assert!(editor.validation.is_ok());
+
+        // escape leaves the binding untouched
+        press(&mut editor, &[KeyCode::Enter]);
+        type_text(&mut editor, "zzz");
+        press(&mut editor, &[KeyCode::Esc]);
+        assert_eq!(editor.macropad.layers[0].buttons[0][1].mapping, "ctrl-c");
+        assert_eq!(editor.mode, Mode::Navigate);
+    }
+
+    #[test]
+    fn picker() {
+        let mut editor = Editor::new(macropad(), "test.ron", None, None);
+        press(&mut editor, &[KeyCode::Char('x'), KeyCode::Char('a')]);
+        type_text(&mut editor, "ctrl");
+        press(&mut editor, &[KeyCode::Enter, KeyCode::Char('a')]);
+        type_text(&mut editor, "pageup");
+        press(&mut editor, &[KeyCode::Enter, KeyCode::Char('a')]);
+        type_text(&mut editor, "media");
+        press(&mut editor, &[KeyCode::Down, KeyCode::Enter]);
+        assert_eq!(
+            editor.macropad.layers[0].buttons[0][0].mapping,
+            "ctrl-pageup,previous"
+        );
+    }
+
+    #[test]
+    fn live_validation() {
+        // the 8890 has a single layer
+        let mut mp = macropad();
+        mp.layers.truncate(1);
+        let mut editor = Editor::new(mp, "test.ron", profile(0x8890), None);
+        assert!(editor.validation.is_ok());
+        press(&mut editor, &[KeyCode::Enter, KeyCode::Backspace]);
+        type_text(&mut editor, "1,2,3,4,5,6");
+        press(&mut editor, &[KeyCode::Enter]);
+        assert!(editor.validation.is_err());
+
+        // invalid configurations are not programmed
+        press(&mut editor, &[KeyCode::Char('w')]);
+        assert!(editor.status.starts_with("not programming"));
+
+        // cleared bindings are invalid too
+        press(&mut editor, &[KeyCode::Char('x')]);
+        assert!(editor.validation.is_err());
+
+        press(&mut editor, &[KeyCode::Enter]);
+        type_text(&mut editor, "1,2");
+        press(&mut editor, &[KeyCode::Enter]);
+        assert_eq!(editor.validation, Ok(Vec::new()));
+
+        // the 8890 ignores delays, which is reported instead of printed
+        press(&mut editor, &[KeyCode::Char('d'), KeyCode::Backspace]);
+        type_text(&mut editor, "100");
+        press(&mut editor, &[KeyCode::Enter]);
+        let warnings = editor.validation.clone().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("delay"), "{warnings:?}");
+    }
+
+    #[test]
+    fn program_callback() {
+        let mut programmed = 0;
+        {
+            let mut editor = Editor::new(
+                macropad(),
+                "test.ron",
+                None,
+                Some(Box::new(|_| {
+                    programmed += 1;
+                    Ok(())
+                })),
+            );
+            // typing w in a mapping does not program
+            press(
+                &mut editor,
+                &[
+                    KeyCode::Enter,
+                    KeyCode::Char('w'),
+                    KeyCode::Backspace,
+                    KeyCode::Enter,
+                ],
+            );
+            assert!(!editor.repaint);
+            press(&mut editor, &[KeyCode::Char('w')]);
+            assert_eq!(editor.status, "successfully programmed device");
+            assert!(editor.repaint);
+        }
+        assert_eq!(programmed, 1);
+    }
+
+    #[test]
+    fn quit_with_unsaved_changes() {
+        let mut editor = Editor::new(macropad(), "test.ron", None, None);
+        press(&mut editor, &[KeyCode::Char('x'), KeyCode::Char('q')]);
+        assert!(!editor.quit);
+        press(&mut editor, &[KeyCode::Char('q')]);
+        assert!(editor.quit);
+    }
+}
@@ -0,0 +1,771 @@
+use crate::mapping::{Device, Macropad, Mapping};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use ron::extensions::Extensions;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use std::ops::Range;
+
+/// Indentation of a nesting level
+const INDENT: &str = "    ";
+
+/// Fields whose value is written one entry per line
+const BLOCK_FIELDS: [&str; 5] = ["device", "layers", "buttons", "knobs", "key_map"];
+
+/// Piece of a configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// `(`, `)`, `[`, `]`, `{`, `}`, `,` or `:`
+    Punct(char),
+    /// identifier, number or string
+    Atom(String),
+    /// comment
+    Comment(String),
+    /// attribute such as `#![enable(implicit_some)]`
+    Attribute(String),
+}
+
+/// Comment kept with the entry that follows it
+#[derive(Debug, Default, Clone)]
+struct Comment {
+    /// comment text including `//` or `/* */`
+    text: String,
+    /// whether an empty line precedes it
+    blank_before: bool,
+}
+
+/// Token with the number of line breaks before it
+type Lexeme = (Token, usize);
+
+/// Field or element of a list, struct or tuple
+#[derive(Debug, Default)]
+struct Entry {
+    /// comments on the lines before the entry
+    comments: Vec<Comment>,
+    /// whether an empty line precedes the entry
+    blank_before: bool,
+    /// field name when the entry is `name: value`
+    name: Option<String>,
+    /// value, eg. an identifier followed by a group for `Some(...)`
+    value: Vec<Value>,
+    /// comment on the line of the entry
+    trailing: Option<String>,
+}
+
+/// Part of a value
+#[derive(Debug)]
+enum Value {
+    Atom(String),
+    Group(Group),
+}
+
+/// List, struct or tuple
+#[derive(Debug)]
+struct Group {
+    open: char,
+    close: char,
+    entries: Vec<Entry>,
+    /// comments after the last entry
+    comments: Vec<Comment>,
+}
+
+/// Canonical formatting of configuration files. Mappings are canonicalized,
+/// the grids of buttons aligned in columns and comments kept
+pub struct Format {}
+
+impl Format {
+    /// Returns the configuration in canonical format
+    ///
+    /// #Arguments
+    /// `data` - configuration in ron format
+    ///
+    pub fn format(data: &str) -> Result<String> {
+        let macropad = Mapping::parse(data).context("parsing configuration")?;
+        let (attributes, root, comments) = Self::parse(data)?;
+        Self::print(&attributes, &root, &comments, &macropad)
+    }
+
+    /// Returns the Macropad in canonical format, keeping the comments of the
+    /// configuration it replaces. Comments of bindings and fields that are no longer
+    /// in the Macropad are dropped
+    ///
+    /// #Arguments
+    /// `original` - configuration in ron format being replaced
+    /// `macropad` - configuration to write
+    ///
+    pub fn update(original: &str, macropad: &Macropad) -> Result<String> {
+        let (attributes, old, comments) =
+            Self::parse(original).context("parsing original configuration")?;
+        let (_, mut root, _) = Self::parse(&Mapping::to_ron(macropad))?;
+        Self::keep_comments(&old, &mut root);
+        Self::print(&attributes, &root, &comments, macropad)
+    }
+
+    /// Returns the configuration with its device section replaced, leaving the rest
+    /// of the file as it is. Comments of the device section are kept
+    ///
+    /// #Arguments
+    /// `original` - configuration in ron format
+    /// `device` - device section to write
+    ///
+    pub fn replace_device(original: &str, device: &Device) -> Result<String> {
+        let macropad = Mapping::parse(original).context("parsing configuration")?;
+        let (tokens, spans) = Self::tokenize_with_spans(original)?;
+
+        // the device field of the root
+        let mut depth = 0;
+        let mut field = None;
+        for (k, (token, _)) in tokens.iter().enumerate() {
+            match token {
+                Token::Punct('(' | '[' | '{') => depth += 1,
+                Token::Punct(')' | ']' | '}') => depth -= 1,
+                Token::Atom(name)
+                    if depth == 1
+                        && name == "device"
+                        && matches!(tokens.get(k + 1), Some((Token::Punct(':'), _))) =>
+                {
+                    field = Some(k);
+                    break;
+                }
+                _ => (),
+            }
+        }
+        let start = field.ok_or_else(|| anyhow!("no device section in the configuration"))?;
+        let mut end = start;
+        let old = Self::parse_entry(&tokens, &mut end)?;
+
+        // optional values are written without Some(...), like in the shipped mapping
+        let pretty = PrettyConfig::new().extensions(Extensions::IMPLICIT_SOME);
+        let ron = to_string_pretty(device, pretty)?;
+        let new_tokens: Vec<Lexeme> = Self::tokenize(&format!("device: {ron}"))?
+            .into_iter()
+            .filter(|(t, _)| !matches!(t, Token::Attribute(_)))
+            .collect();
+        let mut new = Self::parse_entry(&new_tokens, &mut 0)?;
+        Self::keep_comments(&old, &mut new);
+
+        let chars: Vec<char> = original.chars().collect();
+        let mut out: String = chars[..spans[start].start].iter().collect();
+        out += &Self::entry(&new, None, 1);
+        out.extend(&chars[spans[end - 1].end..]);
+
+        let updated = Mapping::parse(&out).context("parsing updated configuration")?;
+        let expected = Macropad {
+            device: device.clone(),
+            layers: macropad.layers,
+        };
+        ensure!(
+            Mapping::to_ron(&updated) == Mapping::to_ron(&expected),
+            "replacing the device section changed the configuration"
+        );
+        Ok(out)
+    }
+
+    /// Returns the attributes, the root entry and the comments after it
+    ///
+    /// #Arguments
+    /// `data` - configuration in ron format
+    ///
+    fn parse(data: &str) -> Result<(Vec<String>, Entry, Vec<Comment>)> {
+        let tokens = Self::tokenize(data)?;
+        let mut pos = 0;
+        let mut attributes = Vec::new();
+        while let Some((Token::Attribute(attr), _)) = tokens.get(pos) {
+            attributes.push(attr.clone());
+            pos += 1;
+        }
+        let root = Self::parse_entry(&tokens, &mut pos)?;
+        let comments = Self::parse_comments(&tokens, &mut pos);
+        ensure!(
+            pos == tokens.len(),
+            "unexpected {:?} after the configuration",
+            tokens[pos].0
+        );
+        Ok((attributes, root, comments))
+    }
+
+    /// Returns the configuration formatted, checking that it is the Macropad
+    ///
+    /// #Arguments
+    /// `attributes` - attributes before the configuration
+    /// `root` - configuration
+    /// `comments` - comments after the configuration
+    /// `macropad` - configuration the output must parse to
+    ///
+    fn print(
+        attributes: &[String],
+        root: &Entry,
+        comments: &[Comment],
+        macropad: &Macropad,
+    ) -> Result<String> {
+        let mut out = String::new();
+        for attr in attributes {
+            out += attr;
+            out += "\n";
+        }
+        for comment in &root.comments {
+            out += &comment.text;
+            out += "\n";
+        }
+        out += &Self::value(&root.value, Some("root"), 0);
+        if let Some(trailing) = &root.trailing {
+            out += " ";
+            out += trailing;
+        }
+        out += "\n";
+        for comment in comments {
+            if comment.blank_before {
+                out += "\n";
+            }
+            out += &comment.text;
+            out += "\n";
+        }
+
+        // formatting must not change the configuration beyond the mappings
+        let formatted = Mapping::parse(&out).context("parsing formatted configuration")?;
+        ensure!(
+            Mapping::to_ron(&formatted) == Mapping::to_ron(&Self::canonical(macropad.clone())),
+            "formatting changed the configuration"
+        );
+        Ok(out)
+    }
+
+    /// Moves the comments of the entry to the entry replacing it. Fields are matched
+    /// by name, elements of lists by position
+    ///
+    /// #Arguments
+    /// `old` - entry with the comments
+    /// `new` - entry replacing it
+    ///
+    fn keep_comments(old: &Entry, new: &mut Entry) {
+        new.comments = old.comments.iter().map(Comment::clone).collect();
+        new.blank_before = old.blank_before;
+        new.trailing = old.trailing.clone();
+        let old_groups = old.value.iter().filter_map(|v| match v {
+            Value::Group(g) => Some(g),
+            Value::Atom(_) => None,
+        });
+        let new_groups = new.value.iter_mut().filter_map(|v| match v {
+            Value::Group(g) => Some(g),
+            Value::Atom(_) => None,
+        });
+        for (old, new) in old_groups.zip(new_groups) {
+            new.comments = old.comments.iter().map(Comment::clone).collect();
+            for (i, entry) in new.entries.iter_mut().enumerate() {
+                let matching = match &entry.name {
+                    Some(name) => old.entries.iter().find(|e| e.name.as_ref() == Some(name)),
+                    None => old.entries.get(i).filter(|e| e.name.is_none()),
+                };
+                if let Some(matching) = matching {
+                    Self::keep_comments(matching, entry);
+                }
+            }
+        }
+    }
+
+    /// Returns the Macropad with canonical mappings
+    ///
+    /// #Arguments
+    /// `macropad` - configuration
+    ///
+    fn canonical(mut macropad: Macropad) -> Macropad {
+        for layer in macropad.layers.iter_mut() {
+            let knobs = layer
+                .knobs
+                .iter_mut()
+                .flat_map(|k| [&mut k.ccw, &mut k.press, &mut k.cw]);
+            for btn in layer.buttons.iter_mut().flatten().chain(knobs) {
+                btn.mapping = Mapping::canonical(&btn.mapping);
+            }
+        }
+        macropad
+    }
+
+    /// Splits the configuration into tokens
+    ///
+    /// #Arguments
+    /// `data` - configuration in ron format
+    ///
+    fn tokenize(data: &str) -> Result<Vec<Lexeme>> {
+        Ok(Self::tokenize_with_spans(data)?.0)
+    }
+
+    /// Splits the configuration into tokens, also returning the characters each
+    /// token spans
+    ///
+    /// #Arguments
+    /// `data` - configuration in ron format
+    ///
+    fn tokenize_with_spans(data: &str) -> Result<(Vec<Lexeme>, Vec<Range<usize>>)> {
+        let chars: Vec<char> = data.chars().collect();
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        let mut newlines = 0;
+        let mut i = 0;
+        while i < chars.len() {
+            let start = i;
+            match chars[i] {
+                '\n' => {
+                    newlines += 1;
+                    i += 1;
+                    continue;
+                }
+                c if c.is_whitespace() => {
+                    i += 1;
+                    continue;
+                }
+                '/' if chars.get(i + 1) == Some(&'/') => {
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                    let text: String = chars[start..i].iter().collect();
+                    tokens.push((Token::Comment(text.trim_end().to_string()), newlines));
+                }
+                '/' if chars.get(i + 1) == Some(&'*') => {
+                    // block comments nest in ron
+                    let mut depth = 0;
+                    loop {
+                        ensure!(i + 1 < chars.len(), "unterminated comment");
+                        if chars[i] == '/' && chars[i + 1] == '*' {
+                            depth += 1;
+                            i += 2;
+                        } else if chars[i] == '*' && chars[i + 1] == '/' {
+                            depth -= 1;
+                            i += 2;
+                            if depth == 0 {
+                                break;
+                            }
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    tokens.push((Token::Comment(chars[start..i].iter().collect()), newlines));
+                }
+                '#' => {
+                    while i < chars.len() && chars[i] != ']' {
+                        i += 1;
+                    }
+                    ensure!(i < chars.len(), "unterminated attribute");
+                    i += 1;
+                    tokens.push((Token::Attribute(chars[start..i].iter().collect()), newlines));
+                }
+                quote @ ('"' | '\'') => {
+                    i += 1;
+                    while i < chars.len() && chars[i] != quote {
+                        i += if chars[i] == '\\' { 2 } else { 1 };
+                    }
+                    ensure!(i < chars.len(), "unterminated string");
+                    i += 1;
+                    tokens.push((Token::Atom(chars[start..i].iter().collect()), newlines));
+                }
+                'r' if matches!(chars.get(i + 1), Some('"' | '#')) => {
+                    // raw string r#"..."#
+                    let hashes = chars[i + 1..].iter().take_while(|c| **c == '#').count();
+                    let end: String = std::iter::once('"')
+                        .chain(std::iter::repeat_n('#', hashes))
+                        .collect();
+                    let rest: String = chars[i + 2 + hashes..].iter().collect();
+                    let len = rest
+                        .find(&end)
+                        .ok_or_else(|| anyhow!("unterminated raw string"))?;
+                    i += 2 + hashes + rest[..len].chars().count() + end.chars().count();
+                    tokens.push((Token::Atom(chars[start..i].iter().collect()), newlines));
+                }
+                c if "()[]{},:".contains(c) => {
+                    i += 1;
+                    tokens.push((Token::Punct(c), newlines));
+                }
+                _ => {
+                    while i < chars.len()
+                        && !chars[i].is_whitespace()
+                        && !"()[]{},:\"'/".contains(chars[i])
+                    {
+                        i += 1;
+                    }
+                    tokens.push((Token::Atom(chars[start..i].iter().collect()), newlines));
+                }
+            }
+            spans.push(start..i);
+            newlines = 0;
+        }
+        Ok((tokens, spans))
+    }
+
+    /// Returns the comments at the position
+    fn parse_comments(tokens: &[Lexeme], pos: &mut usize) -> Vec<Comment> {
+        let mut comments = Vec::new();
+        while let Some((Token::Comment(text), newlines)) = tokens.get(*pos) {
+            comments.push(Comment {
+                text: text.clone(),
+                blank_before: *newlines > 1,
+            });
+            *pos += 1;
+        }
+        comments
+    }
+
+    /// Returns the entry at the position, up to the `,` or the end of its group
+    fn parse_entry(tokens: &[Lexeme], pos: &mut usize) -> Result<Entry> {
+        let blank_before = tokens.get(*pos).is_some_and(|(_, n)| *n > 1);
+        let mut entry = Entry {
+            comments: Self::parse_comments(tokens, pos),
+            blank_before,
+            ..Default::default()
+        };
+        if let (Some((Token::Atom(name), _)), Some((Token::Punct(':'), _))) =
+            (tokens.get(*pos), tokens.get(*pos + 1))
+        {
+            entry.name = Some(name.clone());
+            *pos += 2;
+        }
+        loop {
+            // comments within a value move before the entry
+            let start = *pos;
+            let comments = Self::parse_comments(tokens, pos);
+            match tokens.get(*pos).map(|(t, _)| t) {
+                Some(Token::Atom(_) | Token::Punct('(' | '[' | '{')) => {
+                    entry.comments.extend(comments)
+                }
+                _ => {
+                    *pos = start;
+                    break;
+                }
+            }
+            match tokens.get(*pos).map(|(t, _)| t) {
+                Some(Token::Atom(atom)) => {
+                    entry.value.push(Value::Atom(atom.clone()));
+                    *pos += 1;
+                }
+                Some(Token::Punct(open @ ('(' | '[' | '{'))) => {
+                    *pos += 1;
+                    entry
+                        .value
+                        .push(Value::Group(Self::parse_group(*open, tokens, pos)?));
+                }
+                _ => break,
+            }
+        }
+        ensure!(
+            !entry.value.is_empty(),
+            "expected a value, found {:?}",
+            tokens.get(*pos).map(|(t, _)| t)
+        );
+        Ok(entry)
+    }
+
+    /// Returns the group after its opening bracket, up to and including its closing one
+    fn parse_group(open: char, tokens: &[Lexeme], pos: &mut usize) -> Result<Group> {
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            _ => '}',
+        };
+        let mut group = Group {
+            open,
+            close,
+            entries: Vec::new(),
+            comments: Vec::new(),
+        };
+        loop {
+            let start = *pos;
+            let comments = Self::parse_comments(tokens, pos);
+            if tokens.get(*pos).map(|(t, _)| t) == Some(&Token::Punct(close)) {
+                group.comments = comments;
+                *pos += 1;
+                return Ok(group);
+            }
+            *pos = start;
+            let mut entry = Self::parse_entry(tokens, pos)?;
+            // the last entry may be followed by comments without a ','
+            let next = tokens[*pos..]
+                .iter()
+                .map(|(t, _)| t)
+                .find(|t| !matches!(t, Token::Comment(_)));
+            match next {
+                Some(Token::Punct(',')) => *pos += 1,
+                Some(Token::Punct(c)) if *c == close => (),
+                other => return Err(anyhow!("expected ',' or '{close}', found {other:?}")),
+            }
+            // a comment on the same line belongs to the entry
+            if let Some((Token::Comment(text), 0)) = tokens.get(*pos) {
+                entry.trailing = Some(text.clone());
+                *pos += 1;
+            }
+            group.entries.push(entry);
+        }
+    }
+
+    /// Returns the value formatted
+    ///
+    /// #Arguments
+    /// `value` - value to format
+    /// `field` - name of the field or list the value belongs to
+    /// `depth` - nesting level of the value
+    ///
+    fn value(value: &[Value], field: Option<&str>, depth: usize) -> String {
+        let mut out = String::new();
+        for (i, v) in value.iter().enumerate() {
+            match v {
+                Value::Atom(atom) => {
+                    if i > 0 {
+                        out += " ";
+                    }
+                    out += &Self::atom(atom, field);
+                }
+                Value::Group(group) => out += &Self::group(group, field, depth),
+            }
+        }
+        out
+    }
+
+    /// Returns the atom, canonicalized when it is a mapping
+    fn atom(atom: &str, field: Option<&str>) -> String {
+        match atom.strip_prefix('"').and_then(|a| a.strip_suffix('"')) {
+            Some(mapping) if field == Some("mapping") && !mapping.contains('\\') => {
+                format!("\"{}\"", Mapping::canonical(mapping))
+            }
+            _ => atom.to_string(),
+        }
+    }
+
+    /// Returns the entry formatted, without its comments
+    fn entry(entry: &Entry, field: Option<&str>, depth: usize) -> String {
+        match &entry.name {
+            Some(name) => format!("{name}: {}", Self::value(&entry.value, Some(name), depth)),
+            None => Self::value(&entry.value, field, depth),
+        }
+    }
+
+    /// Returns the group formatted, on one line or with one entry per line
+    ///
+    /// #Arguments
+    /// `group` - group to format
+    /// `field` - name of the field or list the group belongs to
+    /// `depth` - nesting level of the group
+    ///
+    fn group(group: &Group, field: Option<&str>, depth: usize) -> String {
+        let has_comments = !group.comments.is_empty()
+            || group
+                .entries
+                .iter()
+                .any(|e| !e.comments.is_empty() || e.trailing.is_some());
+        let block = has_comments
+            || field.is_some_and(|f| f == "root" || f == "layer" || BLOCK_FIELDS.contains(&f));
+        // elements of lists are named after the list
+        let entry_field = match field {
+            Some("layers") => Some("layer"),
+            Some("buttons" | "key_map") => Some("row"),
+            Some("knobs") => Some("knob"),
+            _ => None,
+        };
+
+        let entries: Vec<String> = group
+            .entries
+            .iter()
+            .map(|e| Self::entry(e, entry_field, depth + 1))
+            .collect();
+        if !block || (entries.is_empty() && group.comments.is_empty()) {
+            return format!("{}{}{}", group.open, entries.join(", "), group.close);
+        }
+        let entries = Self::align(entries);
+
+        let indent = INDENT.repeat(depth + 1);
+        let mut out = String::from(group.open);
+        out += "\n";
+        for (i, (entry, text)) in group.entries.iter().zip(entries).enumerate() {
+            for (j, comment) in entry.comments.iter().enumerate() {
+                if comment.blank_before && (i > 0 || j > 0) {
+                    out += "\n";
+                }
+                out += &format!("{indent}{}\n", comment.text);
+            }
+            if entry.comments.is_empty() && entry.blank_before && i > 0 {
+                out += "\n";
+            }
+            out += &format!("{indent}{text},");
+            if let Some(trailing) = &entry.trailing {
+                out += " ";
+                out += trailing;
+            }
+            out += "\n";
+        }
+        for comment in &group.comments {
+            if comment.blank_before {
+                out += "\n";
+            }
+            out += &format!("{indent}{}\n", comment.text);
+        }
+        out += &INDENT.repeat(depth);
+        out.push(group.close);
+        out
+    }
+
+    /// Aligns the columns of rows written on one line, eg. the rows of buttons
+    ///
+    /// #Arguments
+    /// `rows` - entries of a group written one per line
+    ///
+    fn align(rows: Vec<String>) -> Vec<String> {
+        let cells: Option<Vec<Vec<String>>> = rows.iter().map(|r| Self::cells(r)).collect();
+        let Some(cells) = cells.filter(|c| c.len() > 1) else {
+            return rows;
+        };
+        let columns = cells.iter().map(|c| c.len()).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|j| {
+                cells
+                    .iter()
+                    .filter_map(|c| c.get(j))
+                    .map(|c| c.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        rows.iter()
+            .zip(cells)
+            .map(|(row, cells)| {
+                let (open, close) = (&row[..1], &row[row.len() - 1..]);
+                let last = cells.len() - 1;
+                let cells = cells.iter().enumerate().map(|(j, cell)| {
+                    if j == last {
+                        cell.clone()
+                    } else {
+                        format!("{cell},{:1$}", "", widths[j] - cell.chars().count() + 1)
+                    }
+                });
+                format!("{open}{}{close}", cells.collect::<String>())
+            })
+            .collect()
+    }
+
+    /// Returns the entries of a group written on one line whose entries are
+    /// all groups, None for other values
+    fn cells(row: &str) -> Option<Vec<String>> {
+        let inner = row.strip_prefix(['[', '('])?.strip_suffix([']', ')'])?;
+        if inner.is_empty() || row.contains('\n') {
+            return None;
+        }
+        // split at the commas outside nested groups and strings
+        let mut cells = Vec::new();
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut cell = String::new();
+        for c in inner.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '(' | '[' | '{' if !in_string => depth += 1,
+                ')' | ']' | '}' if !in_string => depth -= 1,
+                ',' if !in_string && depth == 0 => {
+                    cells.push(cell.trim().to_string());
+                    cell.clear();
+                    continue;
+                }
+                _ => (),
+            }
+            cell.push(c);
+        }
+        cells.push(cell.trim().to_string());
+        cells
+            .iter()
+            .all(|c| c.starts_with(['(', '[']))
+            .then_some(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Orientation;
+    use crate::format::Format;
+    use crate::mapping::Mapping;
+
+    #[test]
+    fn shipped_mapping_is_formatted() -> anyhow::Result<()> {
+        let data = include_str!("../mapping.ron");
+        assert_eq!(Format::format(data)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn format() -> anyhow::Result<()> {
+        let data = r#"(device: (orientation: Normal, rows: 2, cols: 2, knobs: 1), // 2x2 pad
+    layers: [
+        /* layer 1 */ (buttons: [[(delay: 0, mapping: "Ctrl-A"), (delay:0,mapping: "b")],
+                [(delay: 0, mapping: "alt-ctrl-F4,x"), (delay: 0, mapping: "shift-click")]],
+
+            knobs: [(ccw: (delay: 0, mapping: "VolumeDown"), press: (delay: 0, mapping: "mute"), cw: (delay: 0, mapping: "volumeup"))]
+            // end of layer 1
+        )
+    ])
+// trailing
+"#;
+        let expected = r#"(
+    device: (
+        orientation: Normal,
+        rows: 2,
+        cols: 2,
+        knobs: 1,
+    ), // 2x2 pad
+    layers: [
+        /* layer 1 */
+        (
+            buttons: [
+                [(delay: 0, mapping: "ctrl-a"),        (delay: 0, mapping: "b")],
+                [(delay: 0, mapping: "ctrl-alt-f4,x"), (delay: 0, mapping: "shift-click")],
+            ],
+
+            knobs: [
+                (ccw: (delay: 0, mapping: "volumedown"), press: (delay: 0, mapping: "mute"), cw: (delay: 0, mapping: "volumeup")),
+            ],
+            // end of layer 1
+        ),
+    ],
+)
+// trailing
+"#;
+        let formatted = Format::format(data)?;
+        assert_eq!(formatted, expected);
+        assert_eq!(Format::format(&formatted)?, formatted, "formatting again");
+
+        assert!(Format::format("(device: (").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn update_keeps_comments() -> anyhow::Result<()> {
+        let data = include_str!("../mapping.ron");
+        let mut macropad = Mapping::parse(data)?;
+        macropad.layers[0].buttons[0][0].mapping = "Ctrl-X".to_string();
+        macropad.layers.pop();
+
+        let updated = Format::update(data, &macropad)?;
+        for comment in ["// layer 1", "// Normal, Clockwise"] {
+            assert!(updated.contains(comment), "{comment} dropped");
+        }
+        let parsed = Mapping::parse(&updated)?;
+        assert_eq!(parsed.layers[0].buttons[0][0].mapping, "ctrl-x");
+        assert_eq!(parsed.layers.len(), macropad.layers.len());
+        assert_eq!(Format::format(&updated)?, updated, "formatting again");
+        Ok(())
+    }
+
+    #[test]
+    fn replace_device() -> anyhow::Result<()> {
+        let data = include_str!("../mapping.ron");
+        let mut device = Mapping::parse(data)?.device;
+        device.orientation = Orientation::UpsideDown;
+        device.key_map = Some(vec![vec![2, 1, 3], vec![4, 5, 6]]);
+
+        let updated = Format::replace_device(data, &device)?;
+        let expected = data
+            .replace("orientation: Normal", "orientation: UpsideDown")
+            .replace(
+                "knobs: 1,\n    ),",
+                "knobs: 1,\n        key_map: [\n            [2, 1, 3],\n            [4, 5, 6],\n        ],\n    ),",
+            );
+        assert_eq!(updated, expected);
+        assert!(Format::replace_device("(layers: [])", &device).is_err());
+        Ok(())
+    }
+}
@@ -2,27 +2,23 @@ use crate::{
     consts,
     decoder::{Decoder, KeyMapping},
     keyboard::{
-        Configuration, Keyboard, LedColor, MediaCode, Messages, Modifier, MouseAction, MouseButton,
-        WellKnownCode,
+        transport::Transport, Configuration, Keyboard, LedColor, LedMode, MediaCode, Messages,
+        Modifier, MouseEvent, WellKnownCode,
     },
-    mapping::Macropad,
+    mapping::{Macropad, Mapping},
+    profile::{DeviceProfile, Probe},
 };
 use anyhow::{anyhow, ensure, Result};
 use log::{debug, info};
 use num::ToPrimitive;
-use rusb::{Context, DeviceHandle};
 use std::str::FromStr;
 
 /// 0x884x type keyboard
 pub struct Keyboard884x {
-    /// rusb device handle
-    handle: Option<DeviceHandle<Context>>,
-    /// address of out endpoint
-    out_endpoint: u8,
-    /// address of in endpoint
-    in_endpoint: u8,
-    /// product id
-    pid: u16,
+    /// transport to the device
+    transport: Option<Box<dyn Transport>>,
+    /// profile of the device
+    profile: DeviceProfile,
 }
 
 impl Configuration for Keyboard884x {
@@ -33,11 +29,6 @@ impl Configuration for Keyboard884x {
         self.send(&self.device_type())?;
         self.recieve(&mut buf)?;
         let device_info = Decoder::get_device_info(&buf);
-        info!(
-            "OUT: 0x{:02x} IN: 0x{:02x}",
-            self.get_out_endpoint(),
-            self.get_in_endpoint()
-        );
         debug!(
             "number of keys: {} number of rotary encoders: {}",
             device_info.num_keys, device_info.num_encoders
@@ -67,7 +58,7 @@ impl Configuration for Keyboard884x {
             }
         } else {
             // read keys for all layers
-            for i in 1..=consts::NUM_LAYERS {
+            for i in 1..=self.profile.layers {
                 self.send(&self.read_config(device_info.num_keys, device_info.num_encoders, i))?;
                 info!("reading keys for layer {i}");
                 let data = self.read_config(device_info.num_keys, device_info.num_encoders, i);
@@ -87,7 +78,7 @@ impl Configuration for Keyboard884x {
         }
 
         // process responses from device
-        let rows_cols = Self::guestimate_rows_cols(device_info.num_keys)?;
+        let rows_cols = self.profile.geometry(device_info.num_keys)?;
         let mut mp = Macropad::new(rows_cols.0, rows_cols.1, device_info.num_encoders);
         let mut knob_idx = 0;
         let mut knob_type = 0;
@@ -147,11 +138,19 @@ impl Configuration for Keyboard884x {
         }
         Ok(mp)
     }
+
+    fn knob_base(&self) -> u8 {
+        self.profile.knob_base
+    }
+
+    fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
 }
 
 impl Messages for Keyboard884x {
     fn read_config(&self, keys: u8, encoders: u8, layer: u8) -> Vec<u8> {
-        if self.pid == 0x8840 {
+        if self.profile.probe == Probe::K8840 {
             vec![
                 0x03, 0xfa, keys, encoders, layer, 0x06, 0x00, 0xcc, 0x80, 0x00, 0xc0, 0xcc, 0x80,
                 0x00, 0x7c, 0xf2, 0x02, 0x69, 0x00, 0x00, 0x00, 0x00, 0x4d, 0x00, 0x14, 0x06, 0xc0,
@@ -171,7 +170,7 @@ impl Messages for Keyboard884x {
     }
 
     fn device_type(&self) -> Vec<u8> {
-        if self.pid == 0x8840 {
+        if self.profile.probe == Probe::K8840 {
             vec![
                 0x03, 0xfb, 0xfb, 0xfb, 0x02, 0x06, 0x2c, 0xd0, 0x80, 0x00, 0xdc, 0xcf, 0x80, 0x00,
                 0xcc, 0xd2, 0x21, 0x01, 0xe0, 0xcf, 0x80, 0x00, 0x2c, 0xd0, 0x80, 0x00, 0x00, 0x00,
@@ -206,6 +205,20 @@ impl Messages for Keyboard884x {
         msg.extend_from_slice(&[0; 61]);
         msg
     }
+
+    fn describe(&self, msg: &[u8]) -> String {
+        match msg {
+            [_, 0xfd, 0xfe, 0xff, ..] => "end programming".to_string(),
+            [_, 0xfd, key_num, layer, 0x05, ..] => {
+                format!("layer {layer} {} delay", self.key_name(*key_num))
+            }
+            [_, 0xfd, key_num, layer, ..] => format!("layer {layer} {}", self.key_name(*key_num)),
+            [_, 0xfe, 0xb0, layer, ..] => format!("layer {layer} LED"),
+            [_, 0xfb, ..] => "device type".to_string(),
+            [_, 0xfa, _, _, layer, ..] => format!("read layer {layer}"),
+            _ => "unknown message".to_string(),
+        }
+    }
 }
 
 impl Keyboard for Keyboard884x {
@@ -233,14 +246,45 @@ impl Keyboard for Keyboard884x {
             println!("Unable perform sanity check - device does not support reading of configuration. Programming macropad.");
         }
 
+        self.send_msgs(&self.program_msgs(macropad)?)?;
+        Ok(())
+    }
+
+    fn set_led(&mut self, mode: u8, layer: u8, color: LedColor) -> Result<()> {
+        self.profile.led_mode(&LedMode::Index(mode))?;
+        self.send(&self.program_led(mode, layer, color))?;
+        self.send(&self.end_program())?;
+        Ok(())
+    }
+
+    fn transport(&mut self) -> Result<&mut dyn Transport> {
+        match &mut self.transport {
+            Some(transport) => Ok(transport.as_mut()),
+            None => Err(anyhow!("not connected to a device")),
+        }
+    }
+}
+
+impl Keyboard884x {
+    pub fn new(transport: Option<Box<dyn Transport>>, profile: DeviceProfile) -> Result<Self> {
+        let keyboard = Self { transport, profile };
+
+        Ok(keyboard)
+    }
+
+    /// Returns the messages to program the macropad, including the LED settings
+    /// of each layer
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be programmed
+    ///
+    pub(crate) fn program_msgs(&self, macropad: &Macropad) -> Result<Vec<Vec<u8>>> {
         // get our layout of buttons relative to programming orientation
-        let layout = self.get_layout(
-            macropad.device.orientation,
-            macropad.device.rows,
-            macropad.device.cols,
-        )?;
+        let layout = self.device_layout(&macropad.device)?;
         debug!("layout: {layout:?}");
 
+        let mut msgs = Vec::new();
+
         for (i, layer) in macropad.layers.iter().enumerate() {
             let lyr = (i + 1) as u8;
             let mut key_num;
@@ -253,17 +297,17 @@ impl Keyboard for Keyboard884x {
                         i + 1,
                         key_num
                     );
-                    self.send(&self.build_key_msg(&btn.mapping, lyr, key_num, 0)?)?;
+                    msgs.push(self.build_key_msg(&btn.mapping, lyr, key_num, 0)?);
                     if btn.delay > 0 {
                         let mut msg = self.build_key_msg(&btn.mapping, lyr, key_num, btn.delay)?;
                         msg[4] = 5;
-                        self.send(&msg)?;
+                        msgs.push(msg);
                     }
                 }
             }
 
             // TODO: test 9x3 to see if the 3 knobs are top to bottom with key number
-            key_num = 0x10;
+            key_num = self.knob_base();
             for knob in &layer.knobs {
                 debug!(
                     "layer: {} key: 0x{:02x} knob ccw {}",
@@ -271,12 +315,12 @@ impl Keyboard for Keyboard884x {
                     key_num,
                     knob.ccw.mapping
                 );
-                self.send(&self.build_key_msg(&knob.ccw.mapping, lyr, key_num, 0)?)?;
+                msgs.push(self.build_key_msg(&knob.ccw.mapping, lyr, key_num, 0)?);
                 if knob.ccw.delay > 0 {
                     let mut msg =
                         self.build_key_msg(&knob.ccw.mapping, lyr, key_num, knob.ccw.delay)?;
                     msg[4] = 5;
-                    self.send(&msg)?;
+                    msgs.push(msg);
                 }
                 key_num += 1;
 
@@ -286,12 +330,12 @@ impl Keyboard for Keyboard884x {
                     key_num,
                     knob.press.mapping
                 );
-                self.send(&self.build_key_msg(&knob.press.mapping, lyr, key_num, 0)?)?;
+                msgs.push(self.build_key_msg(&knob.press.mapping, lyr, key_num, 0)?);
                 if knob.press.delay > 0 {
                     let mut msg =
                         self.build_key_msg(&knob.press.mapping, lyr, key_num, knob.press.delay)?;
                     msg[4] = 5;
-                    self.send(&msg)?;
+                    msgs.push(msg);
                 }
                 key_num += 1;
 
@@ -301,54 +345,24 @@ impl Keyboard for Keyboard884x {
                     key_num,
                     knob.cw.mapping
                 );
-                self.send(&self.build_key_msg(&knob.cw.mapping, lyr, key_num, 0)?)?;
+                msgs.push(self.build_key_msg(&knob.cw.mapping, lyr, key_num, 0)?);
                 if knob.cw.delay > 0 {
                     let mut msg =
                         self.build_key_msg(&knob.cw.mapping, lyr, key_num, knob.cw.delay)?;
                     msg[4] = 5;
-                    self.send(&msg)?;
+                    msgs.push(msg);
                 }
                 key_num += 1;
             }
-            self.send(&self.end_program())?;
-        }
-        Ok(())
-    }
+            msgs.push(self.end_program());
 
-    fn set_led(&mut self, mode: u8, layer: u8, color: LedColor) -> Result<()> {
-        self.send(&self.program_led(mode, layer, color))?;
-        self.send(&self.end_program())?;
-        Ok(())
-    }
-
-    fn get_handle(&self) -> &DeviceHandle<Context> {
-        self.handle.as_ref().unwrap()
-    }
-
-    fn get_out_endpoint(&self) -> u8 {
-        self.out_endpoint
-    }
-
-    fn get_in_endpoint(&self) -> u8 {
-        self.in_endpoint
-    }
-}
-
-impl Keyboard884x {
-    pub fn new(
-        handle: Option<DeviceHandle<Context>>,
-        out_endpoint: u8,
-        in_endpoint: u8,
-        pid: u16,
-    ) -> Result<Self> {
-        let keyboard = Self {
-            handle,
-            out_endpoint,
-            in_endpoint,
-            pid,
-        };
-
-        Ok(keyboard)
+            if let Some(led) = &layer.led {
+                let mode = self.profile.led_mode(&led.mode)?;
+                msgs.push(self.program_led(mode, lyr, led.color.unwrap_or(LedColor::Red)));
+                msgs.push(self.end_program());
+            }
+        }
+        Ok(msgs)
     }
 
     fn build_key_msg(
@@ -358,7 +372,14 @@ impl Keyboard884x {
         key_pos: u8,
         delay: u16,
     ) -> Result<Vec<u8>> {
+        // one key type for the whole sequence, refuse what cannot be sent as written
+        Mapping::check_key_classes(key_chord, Some(&self.profile))?;
         let keys: Vec<_> = key_chord.split(',').collect();
+        ensure!(
+            keys.len() <= self.profile.max_key_presses,
+            "maximum key presses for this macropad is {}",
+            self.profile.max_key_presses
+        );
         let mut msg = vec![0x03, 0xfd, key_pos, layer, 0x01];
         msg.extend_from_slice(&[0; 5]);
         msg.extend_from_slice(&[keys.len().try_into()?]);
@@ -369,12 +390,18 @@ impl Keyboard884x {
             msg[6] = bytes[1];
         }
 
-        let mut cnt = 0;
         let mut mouse_action = 0u8;
         let mut mouse_click = 0u8;
         let mut media_key = false;
         let mut media_val = 0u8;
         for binding in &keys {
+            if let Some(event) = MouseEvent::parse(binding)? {
+                let (modifier, buttons, wheel) = event.bits();
+                (mouse_click, mouse_action) = (buttons, wheel);
+                msg[4] = 0x03;
+                msg.extend_from_slice(&[modifier, 0x00]);
+                continue;
+            }
             let kc: Vec<_> = binding.split('-').collect();
             let mut m_c = 0x00u8;
             let mut wkk = 0x00;
@@ -395,26 +422,18 @@ impl Keyboard884x {
                         msg[10] = 0x02;
                     }
                     media_key = true;
-                } else if let Ok(a) = MouseButton::from_str(key) {
-                    mouse_click =
-                        2u32.pow(<MouseButton as ToPrimitive>::to_u8(&a).unwrap().into()) as u8;
-                    msg[4] = 0x03;
-                } else if let Ok(a) = MouseAction::from_str(key) {
-                    match a {
-                        MouseAction::WheelUp => mouse_action = 0x01,
-                        MouseAction::WheelDown => mouse_action = 0xff,
-                        _ => (),
-                    }
-                    msg[4] = 0x03;
                 }
             }
             msg.extend_from_slice(&[m_c, wkk]);
-            cnt += 1;
         }
 
-        for _i in 0..=(consts::MAX_KEY_PRESSES_884X - cnt) {
-            msg.extend_from_slice(&[0x00; 2]);
-        }
+        // remaining key slots and the last 18 bytes are always 0
+        ensure!(
+            msg.len() <= consts::PACKET_SIZE,
+            "{} key presses do not fit in a message",
+            keys.len()
+        );
+        msg.resize(consts::PACKET_SIZE, 0);
 
         if media_key {
             msg[12] = media_val;
@@ -427,9 +446,6 @@ impl Keyboard884x {
             msg[15] = mouse_action;
         }
 
-        // last 18 bytes are always 0
-        msg.extend_from_slice(&[0; 18]);
-
         Ok(msg)
     }
 
@@ -455,27 +471,30 @@ impl Keyboard884x {
         }
         Ok((row.into(), col.into()))
     }
-
-    fn guestimate_rows_cols(num_keys: u8) -> Result<(u8, u8)> {
-        match num_keys {
-            6 => Ok((2, 3)),
-            9 => Ok((3, 3)),
-            12 => Ok((3, 4)),
-            15 => Ok((3, 5)),
-            _ => Err(anyhow!("unable to guess rows/cols for {num_keys}")),
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{consts, keyboard::k884x::Keyboard884x, keyboard::Messages, LedColor};
+    use crate::{
+        consts,
+        decoder::Decoder,
+        keyboard::k884x::Keyboard884x,
+        keyboard::Messages,
+        keyboard::{LedColor, LedMode},
+        mapping::{Led, Macropad},
+        profile::Profiles,
+    };
+
+    fn keyboard() -> anyhow::Result<Keyboard884x> {
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8842)?.clone();
+        Keyboard884x::new(None, profile)
+    }
 
     #[test]
     fn ctrl_a_ctrl_s() -> anyhow::Result<()> {
         // ctrl-a,ctrl-s
         // 03 fd 01 01 01 00 00 00     00 00 02 01 04 01 16 00   00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("ctrl-a,ctrl-s", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -484,12 +503,16 @@ mod tests {
         assert_eq!(msg[12], 0x04, "checking for 'a' key");
         assert_eq!(msg[13], 0x01, "checking for ctrl modifier");
         assert_eq!(msg[14], 0x16, "checking for 's' key");
+
+        // sequences longer than the device supports are not cut off
+        let too_long = vec!["a"; 29].join(",");
+        assert!(kbd.build_key_msg(&too_long, 1u8, 1u8, 0).is_err());
         Ok(())
     }
 
     #[test]
     fn well_known_key() -> anyhow::Result<()> {
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("a", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -502,7 +525,7 @@ mod tests {
     #[test]
     fn volume_down() -> anyhow::Result<()> {
         // 03 fd 10 01 02 00 00 00     00 00 02 ea 0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("volumedown", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -513,13 +536,14 @@ mod tests {
         assert_eq!(msg[10], 0x02, "checking byte 10");
         assert_eq!(msg[11], 0xea, "checking byte 11");
         assert_eq!(msg[12], 0x00, "checking byte 12");
+        assert!(kbd.build_key_msg("a,volumedown", 1u8, 1u8, 0).is_err());
         Ok(())
     }
 
     #[test]
     fn mouse_ctrl_plus() -> anyhow::Result<()> {
         // 03 fd 01 02 03 00 00 00     00 00 01 01 00 00 00 01 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("ctrl-wheelup", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -536,7 +560,7 @@ mod tests {
     #[test]
     fn mouse_wheelup() -> anyhow::Result<()> {
         // 03 fd 01 02 03 00 00 00     00 00 01 00 00 00 00 01 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("wheelup", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -553,7 +577,7 @@ mod tests {
     #[test]
     fn mouse_ctrl_minus() -> anyhow::Result<()> {
         // 03 fd 02 02 03 00 00 00     00 00 01 01 00 00 00 ff 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("ctrl-wheeldown", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -567,10 +591,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn mouse_events() -> anyhow::Result<()> {
+        let kbd = keyboard()?;
+        let msg = kbd.build_key_msg("shift-click+rclick", 1u8, 1u8, 0)?;
+        assert_eq!(msg[4], 0x03, "checking byte 4");
+        assert_eq!(msg[11], 0x02, "checking byte 11");
+        assert_eq!(msg[12], 0x03, "checking byte 12");
+        assert_eq!(msg[15], 0x00, "checking byte 15");
+        for mapping in [
+            "click",
+            "ctrl-click",
+            "alt-mclick",
+            "click+rclick",
+            "shift-wheeldown",
+        ] {
+            let msg = kbd.build_key_msg(mapping, 1u8, 1u8, 0)?;
+            assert_eq!(Decoder::get_programmed_key_mapping(&msg)?.keys, [mapping]);
+        }
+        assert!(kbd.build_key_msg("rclick-wheeldown", 1u8, 1u8, 0).is_err());
+        Ok(())
+    }
+
     #[test]
     fn mouse_left_click() -> anyhow::Result<()> {
         // 03 fd 01 02 03 00 00 00     00 00 01 00 01 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("click", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -587,7 +633,7 @@ mod tests {
     #[test]
     fn mouse_middle_click() -> anyhow::Result<()> {
         // 03 fd 02 02 03 00 00 00     00 00 01 00 04 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("mclick", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -604,7 +650,7 @@ mod tests {
     #[test]
     fn mouse_right_click() -> anyhow::Result<()> {
         // 03 fd 03 02 03 00 00 00     00 00 01 00 02 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("rclick", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -621,7 +667,7 @@ mod tests {
     #[test]
     fn shift_p() -> anyhow::Result<()> {
         // 03 fd 06 01 01 00 00 00      00 00 01 02 13 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("shift-p", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -638,7 +684,7 @@ mod tests {
     #[test]
     fn win_enter() -> anyhow::Result<()> {
         // 03 fd 11 03 01 00 00 00      00 00 01 08 28 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("win-enter", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -655,7 +701,7 @@ mod tests {
     #[test]
     fn ctrl_shift_v() -> anyhow::Result<()> {
         // 03 fd 01 01 01 00 00 00      00 00 01 03 19 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("ctrl-shift-v", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -672,7 +718,7 @@ mod tests {
     #[test]
     fn ctrl_alt_del() -> anyhow::Result<()> {
         // 03 fd 01 01 01 00 00 00      00 00 01 05 4c 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("ctrl-alt-delete", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -689,7 +735,7 @@ mod tests {
     #[test]
     fn ctrl_alt_f3() -> anyhow::Result<()> {
         // 03 fd 01 01 01 00 00 00      00 00 01 05 3c 00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("ctrl-alt-f3", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -705,7 +751,7 @@ mod tests {
 
     #[test]
     fn led_mode3_blue_layer_3() -> anyhow::Result<()> {
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.program_led(3, 3, LedColor::Blue);
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -724,7 +770,7 @@ mod tests {
 
     #[test]
     fn calculator() -> anyhow::Result<()> {
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("calculator", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -740,7 +786,7 @@ mod tests {
 
     #[test]
     fn back() -> anyhow::Result<()> {
-        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let kbd = keyboard()?;
         let msg = kbd.build_key_msg("webpageback", 1u8, 1u8, 0)?;
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -753,4 +799,40 @@ mod tests {
         assert_eq!(msg[12], 0x02, "checking byte 12");
         Ok(())
     }
+
+    #[test]
+    fn program_layer_led() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(2, 3, 0);
+        mp.layers[1].led = Some(Led {
+            mode: LedMode::Name("backlight".to_string()),
+            color: Some(LedColor::Green),
+        });
+        let msgs = keyboard()?.program_msgs(&mp)?;
+        let leds: Vec<_> = msgs.iter().filter(|m| m[1] == 0xfe).collect();
+        assert_eq!(leds.len(), 1);
+        assert_eq!(leds[0][3], 2, "checking layer");
+        assert_eq!(leds[0][12], 0x41, "checking mode and color");
+
+        mp.layers[1].led = Some(Led {
+            mode: LedMode::Index(6),
+            color: None,
+        });
+        assert!(keyboard()?.program_msgs(&mp).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn describe_msgs() -> anyhow::Result<()> {
+        let kbd = keyboard()?;
+        let msg = kbd.build_key_msg("ctrl-a", 2, 3, 0)?;
+        assert_eq!(kbd.describe(&msg), "layer 2 key 3");
+        let mut msg = kbd.build_key_msg("a", 1, 0x11, 100)?;
+        msg[4] = 5;
+        assert_eq!(kbd.describe(&msg), "layer 1 knob 1 press delay");
+        assert_eq!(kbd.describe(&kbd.end_program()), "end programming");
+        let msg = kbd.program_led(1, 3, LedColor::Red);
+        assert_eq!(kbd.describe(&msg), "layer 3 LED");
+        assert_eq!(kbd.describe(&kbd.device_type()), "device type");
+        Ok(())
+    }
 }
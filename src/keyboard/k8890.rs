@@ -1,27 +1,35 @@
 use crate::{
     consts,
     keyboard::{
-        Configuration, Keyboard, LedColor, MediaCode, Messages, Modifier, MouseAction, MouseButton,
-        WellKnownCode,
+        transport::Transport, Configuration, Keyboard, LedColor, LedMode, MediaCode, Messages,
+        Modifier, MouseEvent, WellKnownCode,
     },
+    profile::DeviceProfile,
     Macropad,
 };
 use anyhow::{anyhow, Result};
 use log::debug;
 use num::ToPrimitive;
-use rusb::{Context, DeviceHandle};
 use std::str::FromStr;
 
 pub struct Keyboard8890 {
-    handle: Option<DeviceHandle<Context>>,
-    out_endpoint: u8,
+    transport: Option<Box<dyn Transport>>,
     led_programmed: bool,
+    profile: DeviceProfile,
 }
 
 impl Configuration for Keyboard8890 {
     fn read_macropad_config(&mut self, _layer: &u8) -> Result<Macropad> {
         Err(anyhow!("not supported for this macropad"))
     }
+
+    fn knob_base(&self) -> u8 {
+        self.profile.knob_base
+    }
+
+    fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
 }
 
 impl Messages for Keyboard8890 {
@@ -41,46 +49,92 @@ impl Messages for Keyboard8890 {
     }
 
     fn end_program(&self) -> Vec<u8> {
-        let last_byte = if self.led_programmed { 0xa1 } else { 0xaa };
-        let mut msg = vec![0x03, 0xaa, last_byte];
-        let size = consts::PACKET_SIZE - msg.len();
-        msg.extend_from_slice(&vec![0; size]);
-        msg
+        Self::end_msg(self.led_programmed)
+    }
+
+    fn describe(&self, msg: &[u8]) -> String {
+        // keys are programmed into the layer of the preceding begin programming message
+        match msg {
+            [_, 0xa1, layer, ..] => format!("begin programming layer {layer}"),
+            [_, 0xaa, 0xa1, ..] => "end programming (LED)".to_string(),
+            [_, 0xaa, ..] => "end programming".to_string(),
+            [_, 0xb0, 0x18, ..] => "LED".to_string(),
+            [_, key_num, 0x11, count, 0x00, ..] => {
+                format!("{} ({count} key presses)", self.key_name(*key_num))
+            }
+            [_, key_num, 0x11, _, press, ..] => {
+                format!("{} key press {press}", self.key_name(*key_num))
+            }
+            [_, key_num, 0x12, ..] => format!("{} media", self.key_name(*key_num)),
+            [_, key_num, 0x13, ..] => format!("{} mouse", self.key_name(*key_num)),
+            _ => "unknown message".to_string(),
+        }
     }
 }
 
 impl Keyboard for Keyboard8890 {
     fn program(&mut self, macropad: &Macropad) -> Result<()> {
-        debug!("programming keyboard - NOTE: hardcoding to layer 1");
-
-        // FIXME: currently hardcoding the layer to 1 as the only 8890 device
-        //        i have seen only has support for one layer. if we know of
-        //        one that has multiple layers, we should refactor this then
-        //
-        // Until multi-layer support is confirmed for this device, reject configs
-        // with more than one layer rather than silently writing them all to the
-        // same physical slot (where the last layer wins and earlier layers are
-        // lost without warning).
-        if macropad.layers.len() > 1 {
+        self.send_msgs(&self.program_msgs(macropad)?)?;
+        debug!("DONE - programming keyboard");
+        Ok(())
+    }
+
+    fn set_led(&mut self, mode: u8, layer: u8, _color: LedColor) -> Result<()> {
+        self.profile.led_mode(&LedMode::Index(mode))?;
+        self.check_layer(layer)?;
+        self.led_programmed = true;
+        self.send(&self.begin_programming(layer))?;
+        self.send(&self.program_led(mode, layer, LedColor::Red))?;
+        self.send(&self.end_program())?;
+        Ok(())
+    }
+
+    fn transport(&mut self) -> Result<&mut dyn Transport> {
+        match &mut self.transport {
+            Some(transport) => Ok(transport.as_mut()),
+            None => Err(anyhow!("not connected to a device")),
+        }
+    }
+}
+
+impl Keyboard8890 {
+    pub fn new(transport: Option<Box<dyn Transport>>, profile: DeviceProfile) -> Result<Self> {
+        let keyboard = Self {
+            transport,
+            led_programmed: false,
+            profile,
+        };
+
+        Ok(keyboard)
+    }
+
+    /// Returns the messages to program the macropad. Each layer is programmed into
+    /// its own slot, selected by the layer of the begin programming message
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be programmed
+    ///
+    pub(crate) fn program_msgs(&self, macropad: &Macropad) -> Result<Vec<Vec<u8>>> {
+        // reject configs with more layers than the device has rather than silently
+        // dropping the layers that do not fit
+        if macropad.layers.len() > self.profile.layers.into() {
             return Err(anyhow!(
-                "this device is driven as single-layer but the provided configuration has {} layers. \
-                 all layers would be written to the same physical slot, silently overwriting earlier \
-                 layers. please reduce your mapping.ron to a single layer for the 8890",
-                macropad.layers.len()
+                "this device has {} layer(s) but the provided configuration has {} layers. \
+                 please reduce your mapping.ron to {} layer(s) for the {}",
+                self.profile.layers,
+                macropad.layers.len(),
+                self.profile.layers,
+                self.profile.name
             ));
         }
 
-        self.send(&self.begin_programming(1))?;
-
         // get our layout of buttons relative to programming orientation
-        let layout = self.get_layout(
-            macropad.device.orientation,
-            macropad.device.rows,
-            macropad.device.cols,
-        )?;
+        let layout = self.device_layout(&macropad.device)?;
         debug!("layout: {layout:?}");
 
+        let mut msgs = Vec::new();
         for (i, layer) in macropad.layers.iter().enumerate() {
+            msgs.push(self.begin_programming((i + 1).try_into()?));
             let mut key_num;
             for (row_idx, row) in layer.buttons.iter().enumerate() {
                 for (col_idx, btn) in row.iter().enumerate() {
@@ -91,84 +145,61 @@ impl Keyboard for Keyboard8890 {
                         i + 1,
                         key_num
                     );
-                    let keys: Vec<_> = btn.mapping.split(',').collect();
-                    if keys.len() > consts::MAX_KEY_PRESSES_8890 {
-                        return Err(anyhow!(
-                            "maximum key presses for this macropad is {}",
-                            consts::MAX_KEY_PRESSES_8890
-                        ));
-                    }
-                    for msg in self.map_key(btn.mapping.to_string(), key_num)? {
-                        self.send(&msg)?;
-                    }
+                    msgs.extend(self.map_button(&btn.mapping, key_num)?);
                 }
             }
-            key_num = 0x0du8;
+            key_num = self.knob_base();
             for knob in &layer.knobs {
                 debug!(
                     "programming knob ccw: {} cw: {} push: {}",
                     knob.ccw.mapping, knob.cw.mapping, knob.press.mapping
                 );
-                let mut btn;
-                for i in 0..3 {
-                    match i {
-                        0 => btn = knob.ccw.clone(),
-                        1 => btn = knob.press.clone(),
-                        2 => btn = knob.cw.clone(),
-                        _ => unreachable!("should not get here"),
-                    }
-                    let keys: Vec<_> = btn.mapping.split(',').collect();
-                    if keys.len() > consts::MAX_KEY_PRESSES_8890 {
-                        return Err(anyhow!(
-                            "maximum key presses for this macropad is {}",
-                            consts::MAX_KEY_PRESSES_8890
-                        ));
-                    }
-                    for msg in self.map_key(btn.mapping.to_string(), key_num)? {
-                        self.send(&msg)?;
-                    }
+                for btn in [&knob.ccw, &knob.press, &knob.cw] {
+                    msgs.extend(self.map_button(&btn.mapping, key_num)?);
                     key_num += 1;
                 }
             }
         }
-        self.send(&self.end_program())?;
-        debug!("DONE - programming keyboard");
-        Ok(())
-    }
-
-    fn set_led(&mut self, mode: u8, layer: u8, _color: LedColor) -> Result<()> {
-        if mode > 2 {
-            return Err(anyhow!("macropad supports modes 0, 1, and 2 only"));
+        msgs.push(self.end_program());
+
+        // LEDs are set for the whole device, the same way as the led command does
+        if let Some(led) = &macropad.device.led {
+            let mode = self.profile.led_mode(&led.mode)?;
+            msgs.push(self.begin_programming(1));
+            msgs.push(self.program_led(mode, 1, LedColor::Red));
+            msgs.push(Self::end_msg(true));
         }
-        self.led_programmed = true;
-        self.send(&self.begin_programming(layer))?;
-        self.send(&self.program_led(mode, layer, LedColor::Red))?;
-        self.send(&self.end_program())?;
-        Ok(())
-    }
-
-    fn get_handle(&self) -> &DeviceHandle<Context> {
-        self.handle.as_ref().unwrap()
+        Ok(msgs)
     }
 
-    fn get_out_endpoint(&self) -> u8 {
-        self.out_endpoint
+    fn end_msg(led_programmed: bool) -> Vec<u8> {
+        let last_byte = if led_programmed { 0xa1 } else { 0xaa };
+        let mut msg = vec![0x03, 0xaa, last_byte];
+        let size = consts::PACKET_SIZE - msg.len();
+        msg.extend_from_slice(&vec![0; size]);
+        msg
     }
 
-    fn get_in_endpoint(&self) -> u8 {
-        unimplemented!("reading configuration from this macropad is not supported");
+    fn map_button(&self, mapping: &str, key_num: u8) -> Result<Vec<Vec<u8>>> {
+        let keys: Vec<_> = mapping.split(',').collect();
+        if keys.len() > self.profile.max_key_presses {
+            return Err(anyhow!(
+                "maximum key presses for this macropad is {}",
+                self.profile.max_key_presses
+            ));
+        }
+        self.map_key(mapping.to_string(), key_num)
     }
-}
 
-impl Keyboard8890 {
-    pub fn new(handle: Option<DeviceHandle<Context>>, out_endpoint: u8) -> Result<Self> {
-        let keyboard = Self {
-            handle,
-            out_endpoint,
-            led_programmed: false,
-        };
-
-        Ok(keyboard)
+    fn check_layer(&self, layer: u8) -> Result<()> {
+        if layer == 0 || layer > self.profile.layers {
+            return Err(anyhow!(
+                "layer {layer} is not available. {} has {} layer(s)",
+                self.profile.name,
+                self.profile.layers
+            ));
+        }
+        Ok(())
     }
 
     pub fn begin_programming(&self, layer: u8) -> Vec<u8> {
@@ -186,9 +217,18 @@ impl Keyboard8890 {
         for (i, key) in kc.iter().enumerate() {
             let mut msg = vec![0x03, key_pos, 0x00, 0x00, 0x00, 0x00, 0x00];
             let mut remaining = consts::PACKET_SIZE - msg.len();
+            if let Some(event) = MouseEvent::parse(key)? {
+                // buttons in msg[3], modifier in msg[5], wheel in msg[6]
+                let (modifier, buttons, wheel) = event.bits();
+                msg[2] = 0x13;
+                msg[3] = buttons;
+                msg[5] = modifier;
+                msg[6] = wheel;
+                msg.extend_from_slice(&vec![0; remaining]);
+                retval.push(msg);
+                continue;
+            }
             let km: Vec<_> = key.split('-').collect();
-            let mut mouse_action = 0u8;
-            let mut mouse_click;
             let mut media_key = false;
             let mut media_val = 0u8;
             //let mut m_c;
@@ -215,19 +255,6 @@ impl Keyboard8890 {
                     msg[3] = (value & 0xFF) as u8;
                     media_val = ((value & 0xFF00) >> 8) as u8;
                     media_key = true;
-                } else if let Ok(a) = MouseButton::from_str(mod_key) {
-                    mouse_click =
-                        2u32.pow(<MouseButton as ToPrimitive>::to_u8(&a).unwrap().into()) as u8;
-                    msg[2] = 0x13;
-                    msg[3] = mouse_click;
-                } else if let Ok(a) = MouseAction::from_str(mod_key) {
-                    match a {
-                        MouseAction::WheelUp => mouse_action = 0x01,
-                        MouseAction::WheelDown => mouse_action = 0xff,
-                        _ => (),
-                    }
-                    msg[2] = 0x13;
-                    msg[6] = mouse_action;
                 } else {
                     // modifier combo (eg. shift-m)
                     let mapping = Keyboard8890::key_mapping(mod_key)?;
@@ -281,12 +308,25 @@ impl Keyboard8890 {
 mod tests {
     use crate::{
         consts,
-        keyboard::{k8890::Keyboard8890, LedColor, Messages},
+        decoder::Decoder,
+        keyboard::{
+            k8890::Keyboard8890,
+            session::{Direction, Packet, Replay, Session},
+            transport::{self, DryRun},
+            Keyboard, LedColor, LedMode, Messages,
+        },
+        mapping::{Led, Macropad},
+        profile::Profiles,
     };
 
+    fn keyboard() -> anyhow::Result<Keyboard8890> {
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8890)?.clone();
+        Keyboard8890::new(None, profile)
+    }
+
     #[test]
     fn test_hello() -> anyhow::Result<()> {
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msgs = kbd.map_key("h,e,l,l,o".to_string(), 4)?;
         println!("{:02x?}", msgs);
         assert_eq!(msgs.len(), 6, "number of messages created");
@@ -323,7 +363,7 @@ mod tests {
 
     #[test]
     fn ctrl_a_ctrl_s() -> anyhow::Result<()> {
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msgs = kbd.map_key("ctrl-a,ctrl-s".to_string(), 3)?;
         println!("{:02x?}", msgs);
         for i in msgs.iter().take(3) {
@@ -347,7 +387,7 @@ mod tests {
 
     #[test]
     fn a_key() -> anyhow::Result<()> {
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msgs = kbd.map_key("a".to_string(), 1)?;
         println!("{:02x?}", msgs);
         assert_eq!(msgs.len(), 2, "number of messages created");
@@ -363,7 +403,7 @@ mod tests {
 
     #[test]
     fn led_mode2() -> anyhow::Result<()> {
-        let mut kbd = Keyboard8890::new(None, 0)?;
+        let mut kbd = keyboard()?;
         kbd.led_programmed = true;
         let msg = kbd.program_led(2, 1, LedColor::Red);
         println!("{:02x?}", msg);
@@ -381,7 +421,7 @@ mod tests {
 
     #[test]
     fn end_programming() -> anyhow::Result<()> {
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msg = kbd.end_program();
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
@@ -394,7 +434,7 @@ mod tests {
     #[test]
     fn volume_up() -> anyhow::Result<()> {
         // 03 01 12 e9 000000...
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msgs = kbd.map_key("volumeup".to_string(), 1)?;
         println!("{:02x?}", msgs);
         assert_eq!(msgs.len(), 1, "number of messages created");
@@ -407,7 +447,7 @@ mod tests {
     #[test]
     fn calculator() -> anyhow::Result<()> {
         // 03 01 12 e9 01 000000...
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msgs = kbd.map_key("calculator".to_string(), 1)?;
         println!("{:02x?}", msgs);
         assert_eq!(msgs.len(), 1, "number of messages created");
@@ -420,7 +460,7 @@ mod tests {
     #[test]
     fn back() -> anyhow::Result<()> {
         // 03 01 12 24 02 000000...
-        let kbd = Keyboard8890::new(None, 0)?;
+        let kbd = keyboard()?;
         let msgs = kbd.map_key("webpageback".to_string(), 1)?;
         println!("{:02x?}", msgs);
         assert_eq!(msgs.len(), 1, "number of messages created");
@@ -429,4 +469,195 @@ mod tests {
         assert_eq!(&expected, &msgs[0][..8], "checking message");
         Ok(())
     }
+
+    #[test]
+    fn decode_round_trip() -> anyhow::Result<()> {
+        let kbd = keyboard()?;
+        for mapping in [
+            "a",
+            "ctrl-a",
+            "ctrl-shift-a",
+            "rctrl-ralt-delete",
+            "win",
+            "h,e,l,l,o",
+            "ctrl-a,ctrl-s",
+            "volumeup",
+            "calculator",
+            "webpageback",
+            "play,volumeup",
+            "volumeup,a,b",
+            "click",
+            "rclick",
+            "mclick",
+            "wheelup",
+            "wheeldown",
+            "ctrl-click",
+            "shift-wheeldown",
+            "click+rclick",
+            "alt-click+mclick",
+            "a,ctrl-wheelup",
+        ] {
+            let msgs = kbd.map_key(mapping.to_string(), 3)?;
+            assert_eq!(Decoder::get_8890_mapping(&msgs)?, mapping);
+        }
+        // buttons and wheel in one key press, and modifiers mouse events cannot hold
+        assert!(kbd.map_key("click-wheelup".to_string(), 3).is_err());
+        assert!(kbd.map_key("win-click".to_string(), 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_errors() -> anyhow::Result<()> {
+        let kbd = keyboard()?;
+        let mut msgs = kbd.map_key("a,b".to_string(), 3)?;
+        msgs.pop();
+        assert!(
+            Decoder::get_8890_mapping(&msgs).is_err(),
+            "missing key press"
+        );
+
+        let mut msgs = kbd.map_key("a,b".to_string(), 3)?;
+        msgs.swap(1, 2);
+        assert!(Decoder::get_8890_mapping(&msgs).is_err(), "out of order");
+
+        let mut msgs = kbd.map_key("a".to_string(), 3)?;
+        msgs.extend(kbd.map_key("volumeup".to_string(), 4)?);
+        assert!(Decoder::get_8890_mapping(&msgs).is_err(), "two keys");
+
+        let mut msgs = kbd.map_key("wheelup".to_string(), 3)?;
+        msgs[0][6] = 0x02;
+        assert!(Decoder::get_8890_mapping(&msgs).is_err(), "unknown wheel");
+        Ok(())
+    }
+
+    #[test]
+    fn program_layers() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 3, 1);
+        for layer in mp.layers.iter_mut() {
+            for btn in layer.buttons.iter_mut().flatten() {
+                btn.mapping = "a".to_string();
+            }
+            for knob in layer.knobs.iter_mut() {
+                for btn in [&mut knob.ccw, &mut knob.press, &mut knob.cw] {
+                    btn.mapping = "b".to_string();
+                }
+            }
+        }
+
+        // the built-in profile only has one layer
+        assert!(keyboard()?.program_msgs(&mp).is_err());
+
+        let mut profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8890)?.clone();
+        profile.layers = 3;
+        let kbd = Keyboard8890::new(None, profile)?;
+        let msgs = kbd.program_msgs(&mp)?;
+        let begins: Vec<_> = msgs.iter().filter(|m| m[1] == 0xa1).map(|m| m[2]).collect();
+        assert_eq!(begins, vec![1, 2, 3]);
+        assert_eq!(msgs.last().unwrap()[..3], [0x03, 0xaa, 0xaa]);
+
+        mp.layers.truncate(1);
+        assert!(keyboard()?.program_msgs(&mp).is_ok());
+
+        // LEDs are programmed after the keys
+        mp.device.led = Some(Led {
+            mode: LedMode::Name("cycle".to_string()),
+            color: None,
+        });
+        let msgs = keyboard()?.program_msgs(&mp)?;
+        let n = msgs.len();
+        assert_eq!(msgs[n - 4][..3], [0x03, 0xaa, 0xaa]);
+        assert_eq!(msgs[n - 2][..4], [0x03, 0xb0, 0x18, 0x02]);
+        assert_eq!(msgs[n - 1][..3], [0x03, 0xaa, 0xa1]);
+        Ok(())
+    }
+
+    #[test]
+    fn led_layer() -> anyhow::Result<()> {
+        let kbd = keyboard()?;
+        kbd.check_layer(1)?;
+        assert!(kbd.check_layer(0).is_err());
+        assert!(kbd.check_layer(2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn describe_msgs() -> anyhow::Result<()> {
+        let kbd = keyboard()?;
+        assert_eq!(
+            kbd.describe(&kbd.begin_programming(1)),
+            "begin programming layer 1"
+        );
+        let msgs = kbd.map_key("ctrl-a".to_string(), 2)?;
+        assert_eq!(kbd.describe(&msgs[0]), "key 2 (1 key presses)");
+        assert_eq!(kbd.describe(&msgs[1]), "key 2 key press 1");
+        let msgs = kbd.map_key("volumeup".to_string(), 0x0e)?;
+        assert_eq!(kbd.describe(&msgs[0]), "knob 1 press media");
+        assert_eq!(
+            kbd.describe(&Keyboard8890::end_msg(true)),
+            "end programming (LED)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn program_dry_run() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 3, 1);
+        mp.layers.truncate(1);
+        for btn in &mut mp.layers[0].buttons[0] {
+            btn.mapping = "a".to_string();
+        }
+        for knob in &mut mp.layers[0].knobs {
+            knob.ccw.mapping = "b".to_string();
+            knob.press.mapping = "c".to_string();
+            knob.cw.mapping = "d".to_string();
+        }
+        assert!(keyboard()?.program(&mp).is_err(), "no device to program");
+
+        let _lock = transport::TEST_INTERRUPTS.lock();
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8890)?.clone();
+        let mut kbd = Keyboard8890::new(Some(Box::new(DryRun::default())), profile)?;
+        kbd.program(&mp)?;
+        Ok(())
+    }
+
+    #[test]
+    fn program_failure() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 3, 1);
+        mp.layers.truncate(1);
+        for btn in &mut mp.layers[0].buttons[0] {
+            btn.mapping = "a".to_string();
+        }
+        let kbd = keyboard()?;
+        let msgs = kbd.program_msgs(&mp)?;
+
+        // the fourth message fails, programming is ended after it
+        let mut session = Session::new(consts::VENDOR_ID, 0x8890);
+        for msg in [
+            &msgs[0],
+            &msgs[1],
+            &msgs[2],
+            &vec![0xff],
+            &kbd.end_program(),
+        ] {
+            session.packets.push(Packet {
+                time_us: 0,
+                direction: Direction::Out,
+                what: kbd.describe(msg),
+                data: msg.clone(),
+            });
+        }
+        let _lock = transport::TEST_INTERRUPTS.lock();
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8890)?.clone();
+        let mut kbd = Keyboard8890::new(Some(Box::new(Replay::new(session))), profile)?;
+        let err = kbd.program(&mp).unwrap_err();
+        assert!(
+            format!("{err:#}").starts_with(&format!("sending {}", kbd.describe(&msgs[3]))),
+            "{err:#}"
+        );
+        assert!(
+            kbd.send(&kbd.end_program()).is_err(),
+            "end programming was sent after the failure"
+        );
+        Ok(())
+    }
 }
@@ -1,15 +1,23 @@
 pub(crate) mod k884x;
 pub(crate) mod k8890;
+pub(crate) mod session;
+pub(crate) mod transport;
 
-use crate::{config, config::Orientation, consts, mapping::Macropad};
+use crate::{
+    config,
+    config::Orientation,
+    mapping::{Device, Macropad},
+    profile::{DeviceProfile, DriverFamily},
+};
+use transport::Transport;
 
 use std::fmt::Display;
 
-use anyhow::{ensure, Result};
+use anyhow::{anyhow, ensure, Context as _, Result};
 use enumset::{EnumSet, EnumSetType};
 use log::debug;
 use num_derive::{FromPrimitive, ToPrimitive};
-use rusb::{Context, DeviceHandle, Error::Timeout};
+use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumIter, EnumMessage, EnumString};
 
 use itertools::Itertools as _;
@@ -43,6 +51,14 @@ pub trait Messages {
     /// unplugged, it retains its settings
     ///
     fn end_program(&self) -> Vec<u8>;
+
+    /// Returns a short description of what the message does, eg. which layer/key
+    /// it programs
+    ///
+    /// #Arguments
+    /// `msg` - message to describe
+    ///
+    fn describe(&self, msg: &[u8]) -> String;
 }
 
 pub trait Configuration {
@@ -82,6 +98,50 @@ pub trait Configuration {
         Ok(layout)
     }
 
+    /// Returns the key numbers of the buttons of the device, from its key map
+    /// when it has one and from its orientation otherwise
+    ///
+    /// #Arguments
+    /// `device` - device configuration
+    ///
+    fn device_layout(&self, device: &Device) -> Result<Vec<Vec<u8>>> {
+        match &device.key_map {
+            Some(key_map) => {
+                device.check_key_map()?;
+                Ok(key_map.clone())
+            }
+            None => self.get_layout(device.orientation, device.rows, device.cols),
+        }
+    }
+
+    /// Returns the key number of the first knob action. Knob actions are numbered
+    /// sequentially from it in the order ccw, press, cw for each knob
+    ///
+    fn knob_base(&self) -> u8;
+
+    /// Returns the profile of the device
+    ///
+    fn profile(&self) -> &DeviceProfile;
+
+    /// Returns the name of the key with the specified key number, eg. "key 3"
+    /// or "knob 1 cw"
+    ///
+    /// #Arguments
+    /// `key_num` - key number used when programming
+    ///
+    fn key_name(&self, key_num: u8) -> String {
+        if key_num < self.knob_base() {
+            return format!("key {key_num}");
+        }
+        let action = key_num - self.knob_base();
+        let action_name = match action % 3 {
+            0 => KnobAction::RotateCCW,
+            1 => KnobAction::Press,
+            _ => KnobAction::RotateCW,
+        };
+        format!("knob {} {action_name}", action / 3 + 1)
+    }
+
     /// Returns the default 'normal' orientation button numbers for programming
     ///
     /// #Arguments
@@ -120,71 +180,107 @@ pub trait Keyboard: Messages + Configuration {
     ///
     fn set_led(&mut self, mode: u8, layer: u8, color: LedColor) -> Result<()>;
 
-    /// Returns the handle of the device
+    /// Returns the transport used to talk to the device, or an error when the
+    /// driver is not connected to one
     ///
-    fn get_handle(&self) -> &DeviceHandle<Context>;
+    fn transport(&mut self) -> Result<&mut dyn Transport>;
 
-    /// Returns the out endpoint of the device (write)
-    ///
-    fn get_out_endpoint(&self) -> u8;
-
-    /// Returns the in endpoint of the device (read)
-    ///
-    fn get_in_endpoint(&self) -> u8;
-
-    /// Sends the specified `msg` over usb to the out endpoint as an
-    /// USB Interrupt out message. Error is through if not all bytes
-    /// of the message could be sent
+    /// Sends the specified `msg` to the device. Error is through, naming the
+    /// message, if not all bytes of the message could be sent
     ///
     /// #Arguments
     /// `msg` - message to be sent
     ///
     fn send(&mut self, msg: &[u8]) -> Result<()> {
-        let written = self.get_handle().write_interrupt(
-            self.get_out_endpoint(),
-            msg,
-            consts::DEFAULT_TIMEOUT,
-        )?;
-        ensure!(written == msg.len(), "not all data written");
-        debug!("msg: {msg:02x?}");
+        let what = self.describe(msg);
+        let written = self
+            .transport()?
+            .write(msg, &what)
+            .with_context(|| format!("sending {what}"))?;
+        ensure!(
+            written == msg.len(),
+            "sending {what}: only {written} of {} bytes written",
+            msg.len()
+        );
+        debug!("msg ({what}): {msg:02x?}");
         debug!("--------------------------------------------------");
         Ok(())
     }
 
-    /// Reads data from macropad and stores it in buf
+    /// Sends the programming messages to the device. When sending fails or is
+    /// interrupted with Ctrl-C part way, programming is ended so the device does
+    /// not stay in programming mode
     ///
     /// #Arguments
-    /// `buf` - buffer to store the data that is read
+    /// `msgs` - messages to be sent, ending programming
     ///
-    fn recieve(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let read =
-            self.get_handle()
-                .read_interrupt(self.get_in_endpoint(), buf, consts::DEFAULT_TIMEOUT);
-
-        let mut bytes_read = 0;
-        if let Err(e) = read {
-            match e {
-                Timeout => {
-                    debug!("timeout on read");
-                    return Ok(0);
-                }
-
-                _ => {
-                    eprintln!("error reading interrupt - {e}");
-                }
+    fn send_msgs(&mut self, msgs: &[Vec<u8>]) -> Result<()> {
+        for (i, msg) in msgs.iter().enumerate() {
+            let result = if transport::interrupted() {
+                Err(anyhow!("interrupted before {}", self.describe(msg)))
+            } else {
+                self.send(msg)
             };
-        } else {
-            bytes_read = read.unwrap();
+            if let Err(e) = result {
+                if i > 0 {
+                    if let Err(end) = self.send(&self.end_program()) {
+                        debug!("ending programming failed - {end:#}");
+                    }
+                }
+                return Err(e);
+            }
         }
+        Ok(())
+    }
 
+    /// Reads data from macropad and stores it in buf
+    ///
+    /// #Arguments
+    /// `buf` - buffer to store the data that is read
+    ///
+    fn recieve(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let bytes_read = self
+            .transport()?
+            .read(buf)
+            .context("receiving from the device")?;
         debug!("bytes read: {bytes_read}");
         debug!("data: {buf:02x?}");
-
         Ok(bytes_read)
     }
 }
 
-#[derive(Debug, Default, ToPrimitive, Clone, Copy, Display, clap::ValueEnum)]
+/// Returns the driver for the device described by the profile
+///
+/// #Arguments
+/// `profile` - profile of the device
+/// `transport` - transport to the device (None when not talking to a device)
+///
+pub fn new_keyboard(
+    profile: &DeviceProfile,
+    transport: Option<Box<dyn Transport>>,
+) -> Result<Box<dyn Keyboard>> {
+    match profile.driver {
+        DriverFamily::K884x => k884x::Keyboard884x::new(transport, profile.clone())
+            .map(|v| Box::new(v) as Box<dyn Keyboard>),
+        DriverFamily::K8890 => k8890::Keyboard8890::new(transport, profile.clone())
+            .map(|v| Box::new(v) as Box<dyn Keyboard>),
+    }
+}
+
+#[derive(
+    Debug,
+    Default,
+    FromPrimitive,
+    ToPrimitive,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Display,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+)]
 pub enum LedColor {
     Red = 0x10,
     Orange = 0x20,
@@ -196,7 +292,34 @@ pub enum LedColor {
     Purple = 0x70,
 }
 
-#[allow(unused)]
+/// LED mode, given either by its number or by its name in the device profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LedMode {
+    Index(u8),
+    Name(String),
+}
+
+impl std::str::FromStr for LedMode {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.parse::<u8>() {
+            Ok(index) => LedMode::Index(index),
+            Err(_) => LedMode::Name(s.to_string()),
+        })
+    }
+}
+
+impl Display for LedMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedMode::Index(index) => write!(f, "{index}"),
+            LedMode::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Display)]
 #[repr(u8)]
 pub enum KnobAction {
@@ -397,9 +520,11 @@ pub enum WellKnownCode {
     F24,
 }
 
-#[allow(dead_code)] // TODO: implement
-#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
-#[strum(ascii_case_insensitive)]
+/// Modifier held while clicking or turning the wheel, the value is its bit in the messages
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, EnumString, EnumIter, Display, ToPrimitive, FromPrimitive,
+)]
+#[strum(ascii_case_insensitive, serialize_all = "lowercase")]
 #[repr(u8)]
 pub enum MouseModifier {
     Ctrl = 0x01,
@@ -408,6 +533,7 @@ pub enum MouseModifier {
 }
 
 #[derive(ToPrimitive, EnumString, Debug, EnumSetType, EnumIter, Display)]
+#[strum(ascii_case_insensitive)]
 pub enum MouseButton {
     #[strum(serialize = "click")]
     Left,
@@ -444,10 +570,64 @@ impl Display for MouseAction {
     }
 }
 
-#[allow(dead_code)] // TODO: implement
+/// Mouse buttons or wheel with an optional modifier, eg. `ctrl-click`, `shift-wheeldown`
+/// or `click+rclick`
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MouseEvent(pub MouseAction, pub Option<MouseModifier>);
 
+impl MouseEvent {
+    /// Returns the mouse event of a key press, None when it has no mouse buttons or
+    /// wheel and an error when they are combined in a way that cannot be sent
+    ///
+    /// #Arguments
+    /// `key` - key press, eg. `ctrl-click`
+    ///
+    pub fn parse(key: &str) -> Result<Option<Self>> {
+        let is_mouse = |part: &str| {
+            part.parse::<MouseButton>().is_ok()
+                || ["wheelup", "wheeldown"].contains(&part.to_lowercase().as_str())
+        };
+        if !key.split(['-', '+']).any(is_mouse) {
+            return Ok(None);
+        }
+
+        let mut parts: Vec<_> = key.split('-').collect();
+        let mut modifier = None;
+        if parts.len() > 1 && !is_mouse(parts[0]) {
+            let m = parts.remove(0);
+            modifier = Some(m.parse::<MouseModifier>().map_err(|_| {
+                anyhow!("{m} cannot be held with mouse events, only ctrl, shift or alt")
+            })?);
+        }
+        let action = match parts.join("+").to_lowercase().as_str() {
+            "wheelup" => MouseAction::WheelUp,
+            "wheeldown" => MouseAction::WheelDown,
+            buttons => MouseAction::Click(
+                buttons
+                    .split('+')
+                    .map(|b| {
+                        b.parse::<MouseButton>().map_err(|_| {
+                            anyhow!("{b} cannot be combined with mouse buttons in {key}")
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+        };
+        Ok(Some(Self(action, modifier)))
+    }
+
+    /// Returns the bits of the modifier, buttons and wheel in the messages
+    pub fn bits(&self) -> (u8, u8, u8) {
+        let Self(action, modifier) = self;
+        let modifier = modifier.map(|m| m as u8).unwrap_or(0);
+        match action {
+            MouseAction::Click(buttons) => (modifier, buttons.as_u8(), 0x00),
+            MouseAction::WheelUp => (modifier, 0x00, 0x01),
+            MouseAction::WheelDown => (modifier, 0x00, 0xff),
+        }
+    }
+}
+
 impl Display for MouseEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let Self(action, modifier) = self;
@@ -0,0 +1,260 @@
+use crate::keyboard::transport::Transport;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, serde_as};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Direction of a recorded packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// sent to the device
+    Out,
+    /// received from the device
+    In,
+}
+
+/// Packet of a recorded session
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Packet {
+    /// Microseconds since the start of the session
+    pub time_us: u64,
+    /// Direction of the packet
+    pub direction: Direction,
+    /// Description of the packet (empty for received packets)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub what: String,
+    /// Bytes of the packet (empty when the device did not answer)
+    #[serde_as(as = "Hex")]
+    pub data: Vec<u8>,
+}
+
+/// USB session with a device, as recorded with --record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// USB vendor id of the device
+    pub vendor_id: u16,
+    /// USB product id of the device
+    pub product_id: u16,
+    /// Seconds since the unix epoch when the session started
+    pub started: u64,
+    /// Packets in the order they were sent/received
+    pub packets: Vec<Packet>,
+}
+
+impl Session {
+    /// Returns an empty session for the device
+    ///
+    /// #Arguments
+    /// `vendor_id` - USB vendor id of the device
+    /// `product_id` - USB product id of the device
+    ///
+    pub fn new(vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            packets: Vec::new(),
+        }
+    }
+
+    /// Reads a recorded session
+    ///
+    /// #Arguments
+    /// `file` - session file
+    ///
+    pub fn read(file: &Path) -> Result<Self> {
+        let data =
+            std::fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+        serde_json::from_str(&data).with_context(|| format!("parsing {}", file.display()))
+    }
+
+    /// Writes the session
+    ///
+    /// #Arguments
+    /// `file` - session file
+    ///
+    pub fn write(&self, file: &Path) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(file, data).with_context(|| format!("writing {}", file.display()))
+    }
+}
+
+/// Records the packets going through another transport. The session is written
+/// when the recorder is dropped, so it is kept even when the command fails
+pub struct Recorder {
+    /// transport doing the actual work
+    inner: Box<dyn Transport>,
+    /// file the session is written to
+    file: PathBuf,
+    /// start of the session
+    start: Instant,
+    /// recorded session
+    session: Session,
+}
+
+impl Recorder {
+    /// Returns the recorder wrapping the transport
+    ///
+    /// #Arguments
+    /// `inner` - transport to the device
+    /// `file` - file to write the session to
+    /// `session` - empty session for the device
+    ///
+    pub fn new(inner: Box<dyn Transport>, file: &Path, session: Session) -> Self {
+        Self {
+            inner,
+            file: file.to_path_buf(),
+            start: Instant::now(),
+            session,
+        }
+    }
+
+    fn record(&mut self, direction: Direction, what: &str, data: &[u8]) {
+        self.session.packets.push(Packet {
+            time_us: self.start.elapsed().as_micros() as u64,
+            direction,
+            what: what.to_string(),
+            data: data.to_vec(),
+        });
+    }
+}
+
+impl Transport for Recorder {
+    fn write(&mut self, msg: &[u8], what: &str) -> Result<usize> {
+        let written = self.inner.write(msg, what)?;
+        self.record(Direction::Out, what, &msg[..written]);
+        Ok(written)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.record(Direction::In, "", &buf[..read]);
+        Ok(read)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        match self.session.write(&self.file) {
+            Ok(()) => eprintln!(
+                "recorded {} packets to {}",
+                self.session.packets.len(),
+                self.file.display()
+            ),
+            Err(e) => eprintln!("Warning - unable to write the recorded session - {e:#}"),
+        }
+    }
+}
+
+/// Plays back a recorded session instead of talking to a device. Sent packets
+/// must match the recording, received packets come from it
+pub struct Replay {
+    /// recorded packets
+    packets: std::vec::IntoIter<Packet>,
+    /// number of packets played back so far
+    count: usize,
+}
+
+impl Replay {
+    /// Returns the transport playing back the session
+    ///
+    /// #Arguments
+    /// `session` - recorded session
+    ///
+    pub fn new(session: Session) -> Self {
+        Self {
+            packets: session.packets.into_iter(),
+            count: 0,
+        }
+    }
+
+    fn next(&mut self, direction: Direction) -> Result<Packet> {
+        self.count += 1;
+        let packet = self.packets.next().ok_or_else(|| {
+            anyhow!(
+                "replay: packet #{} ({direction:?}) is past the end of the recording",
+                self.count
+            )
+        })?;
+        ensure!(
+            packet.direction == direction,
+            "replay: packet #{} is {:?} in the recording but {direction:?} now",
+            self.count,
+            packet.direction
+        );
+        Ok(packet)
+    }
+}
+
+impl Transport for Replay {
+    fn write(&mut self, msg: &[u8], what: &str) -> Result<usize> {
+        let packet = self.next(Direction::Out)?;
+        debug!("replay #{} {what}", self.count);
+        ensure!(
+            packet.data == msg,
+            "replay: packet #{} ({what}) differs from the recording ({})\nrecorded: {:02x?}\nsent:     {msg:02x?}",
+            self.count,
+            packet.what,
+            packet.data
+        );
+        Ok(msg.len())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let packet = self.next(Direction::In)?;
+        let len = packet.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet.data[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::session::{Direction, Recorder, Replay, Session};
+    use crate::keyboard::transport::{DryRun, Transport};
+
+    #[test]
+    fn record_replay() -> anyhow::Result<()> {
+        let file =
+            std::env::temp_dir().join(format!("macropad-tool-session-{}.json", std::process::id()));
+        {
+            let mut recorder = Recorder::new(
+                Box::new(DryRun::default()),
+                &file,
+                Session::new(0x1189, 0x8840),
+            );
+            recorder.write(&[0x03, 0xfb], "device type")?;
+            let mut buf = [0u8; 4];
+            assert_eq!(recorder.read(&mut buf)?, 0);
+        }
+        let session = Session::read(&file)?;
+        std::fs::remove_file(&file)?;
+        assert_eq!(session.product_id, 0x8840);
+        assert_eq!(session.packets.len(), 2);
+        assert_eq!(session.packets[0].direction, Direction::Out);
+        assert_eq!(session.packets[0].data, [0x03, 0xfb]);
+
+        let mut session = session;
+        session.packets[1].data = vec![0x03, 0xfb, 0x0c, 0x02];
+        let mut replay = Replay::new(session.clone());
+        replay.write(&[0x03, 0xfb], "device type")?;
+        let mut buf = [0u8; 8];
+        assert_eq!(replay.read(&mut buf)?, 4);
+        assert_eq!(buf[2], 0x0c);
+        assert!(replay.read(&mut buf).is_err(), "past the end");
+
+        let mut replay = Replay::new(session.clone());
+        assert!(replay.write(&[0x03, 0xfa], "read config").is_err());
+        let mut replay = Replay::new(session);
+        assert!(replay.read(&mut buf).is_err(), "out of order");
+        Ok(())
+    }
+}
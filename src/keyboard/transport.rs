@@ -0,0 +1,391 @@
+use crate::consts;
+
+use anyhow::{anyhow, Context as _, Result};
+use itertools::Itertools as _;
+use log::{debug, info, warn};
+use rusb::{Context, DeviceHandle, UsbContext as _};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of devices currently claimed
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Set when the user pressed Ctrl-C while a device was claimed
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Carries messages between the tool and the macropad
+pub trait Transport {
+    /// Writes the message to the device and returns the number of bytes written
+    ///
+    /// #Arguments
+    /// `msg` - message to be sent
+    /// `what` - short description of the message
+    ///
+    fn write(&mut self, msg: &[u8], what: &str) -> Result<usize>;
+
+    /// Reads data from the device into buf and returns the number of bytes read,
+    /// 0 when the device did not answer in time
+    ///
+    /// #Arguments
+    /// `buf` - buffer to store the data that is read
+    ///
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+/// Timing of the USB transfers
+#[derive(Debug, Clone, Copy)]
+pub struct UsbSettings {
+    /// timeout of a single transfer
+    pub timeout: Duration,
+    /// minimum time between two messages sent to the device
+    pub pace: Duration,
+    /// number of times a failed transfer is retried
+    pub retries: u8,
+}
+
+impl Default for UsbSettings {
+    fn default() -> Self {
+        Self {
+            timeout: consts::DEFAULT_TIMEOUT,
+            pace: Duration::ZERO,
+            retries: consts::DEFAULT_RETRIES,
+        }
+    }
+}
+
+/// What to do after a failed USB transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    /// try the transfer again
+    Retry,
+    /// claim the interface again (opening the device again if it went away) and retry
+    Reclaim,
+    /// give up
+    Fail,
+}
+
+impl Recovery {
+    /// Returns how to recover from the USB error
+    ///
+    /// #Arguments
+    /// `error` - error of the transfer
+    ///
+    fn of(error: rusb::Error) -> Self {
+        match error {
+            rusb::Error::Timeout | rusb::Error::Pipe | rusb::Error::Interrupted => Self::Retry,
+            rusb::Error::NoDevice | rusb::Error::Busy | rusb::Error::Io => Self::Reclaim,
+            _ => Self::Fail,
+        }
+    }
+}
+
+/// Talks to the macropad over USB interrupt transfers
+pub struct UsbTransport {
+    /// rusb device handle
+    handle: DeviceHandle<Context>,
+    /// number of the claimed interface
+    interface: u8,
+    /// address of out endpoint
+    out_endpoint: u8,
+    /// address of in endpoint
+    in_endpoint: u8,
+    /// timing of the transfers
+    settings: UsbSettings,
+    /// end of the last transfer, for pacing
+    last: Option<Instant>,
+    /// true if the kernel driver was detached from the interface
+    detached: bool,
+}
+
+impl UsbTransport {
+    /// Claims the interface of the opened device, detaching the kernel driver
+    /// bound to it, and returns the transport. The interface is released and the
+    /// kernel driver attached again when the transport is dropped
+    ///
+    /// #Arguments
+    /// `handle` - rusb device handle
+    /// `interface` - number of the interface to claim
+    /// `out_endpoint` - address of out endpoint
+    /// `in_endpoint` - address of in endpoint
+    /// `settings` - timing of the transfers
+    ///
+    pub fn open(
+        handle: DeviceHandle<Context>,
+        interface: u8,
+        out_endpoint: u8,
+        in_endpoint: u8,
+        settings: UsbSettings,
+    ) -> Result<Self> {
+        info!("OUT: 0x{out_endpoint:02x} IN: 0x{in_endpoint:02x}");
+        debug!("usb settings: {settings:?}");
+        let mut transport = Self {
+            handle,
+            interface,
+            out_endpoint,
+            in_endpoint,
+            settings,
+            last: None,
+            detached: false,
+        };
+        transport.claim()?;
+        SESSIONS.fetch_add(1, Ordering::SeqCst);
+        Ok(transport)
+    }
+
+    /// Claims the interface, detaching the kernel driver bound to it
+    fn claim(&mut self) -> Result<()> {
+        // not supported on every platform, where there is nothing to detach
+        if let Ok(true) = self.handle.kernel_driver_active(self.interface) {
+            self.handle
+                .detach_kernel_driver(self.interface)
+                .context("detach kernel driver")?;
+            self.detached = true;
+        }
+        self.handle
+            .claim_interface(self.interface)
+            .context("claim interface")?;
+        Ok(())
+    }
+
+    /// Waits until the pacing time since the last transfer has passed
+    fn pace(&self) {
+        if let Some(last) = self.last {
+            let elapsed = last.elapsed();
+            if elapsed < self.settings.pace {
+                thread::sleep(self.settings.pace - elapsed);
+            }
+        }
+    }
+
+    /// Opens the device again at the same USB port, it gets a new address when it
+    /// comes back. Other macropads plugged in at the same time are left alone
+    fn reopen(&mut self) -> Result<()> {
+        let device = self.handle.device();
+        let desc = device.device_descriptor()?;
+        let bus = device.bus_number();
+        let ports = device.port_numbers()?;
+        let device = self
+            .handle
+            .context()
+            .devices()?
+            .iter()
+            .find(|d| {
+                d.bus_number() == bus
+                    && d.port_numbers().is_ok_and(|p| p == ports)
+                    && d.device_descriptor().is_ok_and(|dd| {
+                        dd.vendor_id() == desc.vendor_id() && dd.product_id() == desc.product_id()
+                    })
+            })
+            .ok_or_else(|| anyhow!("device went away"))?;
+        self.handle = device.open().context("opening the device again")?;
+        Ok(())
+    }
+
+    /// Recovers from a failed transfer on the endpoint so it can be retried
+    ///
+    /// #Arguments
+    /// `error` - error of the transfer
+    /// `endpoint` - endpoint of the transfer
+    ///
+    fn recover(&mut self, error: rusb::Error, endpoint: u8) -> Result<()> {
+        match Recovery::of(error) {
+            Recovery::Retry => {
+                if error == rusb::Error::Pipe {
+                    let _ = self.handle.clear_halt(endpoint);
+                }
+            }
+            Recovery::Reclaim => {
+                let _ = self.handle.release_interface(self.interface);
+                thread::sleep(consts::RECLAIM_DELAY);
+                if error == rusb::Error::NoDevice {
+                    self.reopen()?;
+                }
+                self.claim()?;
+            }
+            Recovery::Fail => return Err(error.into()),
+        }
+        Ok(())
+    }
+}
+
+impl Transport for UsbTransport {
+    fn write(&mut self, msg: &[u8], what: &str) -> Result<usize> {
+        let mut attempt = 0;
+        loop {
+            self.pace();
+            let result = self
+                .handle
+                .write_interrupt(self.out_endpoint, msg, self.settings.timeout);
+            self.last = Some(Instant::now());
+            let error = match result {
+                Ok(written) if written == msg.len() => return Ok(written),
+                // the device only takes whole messages, so send it again
+                Ok(written) => {
+                    debug!("{what}: only {written} of {} bytes written", msg.len());
+                    rusb::Error::Interrupted
+                }
+                Err(e) => e,
+            };
+            if attempt >= self.settings.retries || Recovery::of(error) == Recovery::Fail {
+                return Err(anyhow!(error)).with_context(|| {
+                    format!(
+                        "writing to the device failed after {} attempts",
+                        attempt + 1
+                    )
+                });
+            }
+            attempt += 1;
+            warn!(
+                "{what}: {error}, retrying ({attempt}/{})",
+                self.settings.retries
+            );
+            self.recover(error, self.out_endpoint)?;
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .handle
+                .read_interrupt(self.in_endpoint, buf, self.settings.timeout);
+            self.last = Some(Instant::now());
+            let error = match result {
+                Ok(bytes_read) => return Ok(bytes_read),
+                // not every message is answered
+                Err(rusb::Error::Timeout) => {
+                    debug!("timeout on read");
+                    return Ok(0);
+                }
+                Err(e) => e,
+            };
+            if attempt >= self.settings.retries || Recovery::of(error) == Recovery::Fail {
+                return Err(anyhow!(error)).with_context(|| {
+                    format!(
+                        "reading from the device failed after {} attempts",
+                        attempt + 1
+                    )
+                });
+            }
+            attempt += 1;
+            warn!(
+                "read: {error}, retrying ({attempt}/{})",
+                self.settings.retries
+            );
+            self.recover(error, self.in_endpoint)?;
+        }
+    }
+}
+
+impl Drop for UsbTransport {
+    fn drop(&mut self) {
+        if let Err(e) = self.handle.release_interface(self.interface) {
+            debug!("releasing interface {} - {e}", self.interface);
+        }
+        if self.detached {
+            // gives the macropad back to the kernel so it works as a keyboard again
+            if let Err(e) = self.handle.attach_kernel_driver(self.interface) {
+                eprintln!("Warning - unable to attach the kernel driver again - {e}. Plug the macropad in again");
+            }
+        }
+        SESSIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Handles Ctrl-C. While a device is claimed the first Ctrl-C only asks the
+/// current operation to stop, so the device is left in a clean state and given
+/// back to the kernel. Otherwise, or on a second Ctrl-C, the tool exits
+///
+pub fn handle_interrupts() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if SESSIONS.load(Ordering::SeqCst) == 0 || INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("interrupted - finishing up with the device, press Ctrl-C again to exit now");
+    })
+    .context("installing the Ctrl-C handler")
+}
+
+/// Returns true if the user asked to stop with Ctrl-C
+///
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Forgets an earlier Ctrl-C once the interrupted operation has ended, so the
+/// device can be programmed again
+///
+pub fn clear_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Serializes the tests that interrupt or program a device, the interrupt flag
+/// is shared by the whole process
+#[cfg(test)]
+pub static TEST_INTERRUPTS: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Acts as if the user pressed Ctrl-C while a device was claimed
+#[cfg(test)]
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Prints the messages instead of sending them, to show what would be programmed
+#[derive(Default)]
+pub struct DryRun {
+    /// number of messages written so far
+    count: usize,
+}
+
+impl DryRun {
+    /// Returns the formatted message as lines of hex bytes
+    ///
+    /// #Arguments
+    /// `msg` - message to format
+    ///
+    pub fn hex(msg: &[u8]) -> Vec<String> {
+        msg.chunks(16)
+            .map(|chunk| chunk.iter().map(|b| format!("{b:02x}")).join(" "))
+            .collect()
+    }
+}
+
+impl Transport for DryRun {
+    fn write(&mut self, msg: &[u8], what: &str) -> Result<usize> {
+        self.count += 1;
+        println!("#{} {what}", self.count);
+        for line in Self::hex(msg) {
+            println!("    {line}");
+        }
+        Ok(msg.len())
+    }
+
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize> {
+        // there is no device to answer
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::transport::{DryRun, Recovery};
+
+    #[test]
+    fn hex_lines() {
+        let msg: Vec<u8> = (0..65).collect();
+        let lines = DryRun::hex(&msg);
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("00 01 02"));
+        assert_eq!(lines[4], "40");
+    }
+
+    #[test]
+    fn usb_recovery() {
+        assert_eq!(Recovery::of(rusb::Error::Timeout), Recovery::Retry);
+        assert_eq!(Recovery::of(rusb::Error::Pipe), Recovery::Retry);
+        assert_eq!(Recovery::of(rusb::Error::NoDevice), Recovery::Reclaim);
+        assert_eq!(Recovery::of(rusb::Error::Busy), Recovery::Reclaim);
+        assert_eq!(Recovery::of(rusb::Error::Access), Recovery::Fail);
+    }
+}
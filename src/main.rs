@@ -1,19 +1,47 @@
+mod calibrate;
+mod capture;
 mod config;
 mod consts;
+mod convert;
 mod decoder;
+mod doctor;
+mod editor;
+mod format;
 mod keyboard;
 mod mapping;
+mod monitor;
 mod options;
 mod parse;
-
-use crate::consts::PRODUCT_IDS;
+mod profile;
+mod sheet;
+mod state;
+mod udev;
+mod view;
+mod watch;
+
+use crate::calibrate::Calibrate;
+use crate::capture::Capture;
+use crate::convert::Convert;
 use crate::decoder::Decoder;
+use crate::doctor::Doctor;
+use crate::editor::Editor;
+use crate::format::Format;
+use crate::keyboard::session::{Recorder, Replay, Session};
+use crate::keyboard::transport::{self, DryRun, Transport, UsbTransport};
 use crate::keyboard::{
-    k884x, k8890, Keyboard, MediaCode, Modifier, MouseAction, MouseButton, WellKnownCode,
+    Configuration, Keyboard, MediaCode, Modifier, MouseAction, MouseButton, MouseModifier,
+    WellKnownCode,
 };
-use crate::mapping::Macropad;
+use crate::mapping::{Layer, Macropad};
+use crate::monitor::{HidReader, Monitor};
 use crate::options::Options;
 use crate::options::{Command, LedCommand};
+use crate::profile::{DeviceProfile, DriverFamily, Profiles};
+use crate::sheet::{Sheet, SheetFormat};
+use crate::state::State;
+use crate::udev::{Access, UdevRules};
+use crate::view::{View, ViewFormat};
+use crate::watch::Watch;
 
 use anyhow::{anyhow, ensure, Result};
 use indoc::indoc;
@@ -22,9 +50,12 @@ use keyboard::LedColor;
 use log::debug;
 use mapping::Mapping;
 use rusb::{Context, Device, DeviceDescriptor, Direction, TransferType};
+use std::path::Path;
+use std::time::Duration;
 
 use anyhow::Context as _;
 use clap::Parser as _;
+use clap::ValueEnum as _;
 use rusb::UsbContext as _;
 use strum::EnumMessage as _;
 use strum::IntoEnumIterator as _;
@@ -32,7 +63,11 @@ use strum::IntoEnumIterator as _;
 fn main() -> Result<()> {
     env_logger::init();
     let options = Options::parse();
+    transport::handle_interrupts()?;
     debug!("options: {:?}", options.devel_options);
+    let profiles = Profiles::load(&options.profile_files).context("loading device profiles")?;
+    let vendor_id = options.devel_options.vendor_id;
+    let driver = options.devel_options.driver;
 
     match &options.command {
         Command::ShowKeys => {
@@ -63,62 +98,93 @@ fn main() -> Result<()> {
             for b in MouseButton::iter() {
                 println!(" - {b}");
             }
+            println!(
+                "Mouse buttons can be combined (click+rclick) and held with one of: {}",
+                MouseModifier::iter().join(", ")
+            );
         }
 
         Command::Validate {
             config_file,
             product_id,
             device_connected,
+            verbose,
+            view,
+            key_numbers,
         } => {
+            let mut validated_profile = None;
             if *device_connected {
                 debug!("validating with connected device");
-                if let Ok(device) = find_device(consts::VENDOR_ID, None) {
-                    // read the config for buttons/knobs and validate against file
-                    if device.2 != 0x8890 {
-                        // 0x8890 does not support reading configuration
-                        let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
+                if let Ok(device) = find_device(&profiles, vendor_id, None, driver) {
+                    let profile = profiles.resolve(vendor_id, device.2, driver)?;
+                    // number of keys and knobs of the device, read from it when possible and
+                    // from the local record of the last programming otherwise
+                    let mut device_keys = None;
+                    if profile.read_config {
+                        let mut keyboard =
+                            open_keyboard(&options, &profiles).context("opening keyboard")?;
                         let mut buf = vec![0; consts::READ_BUF_SIZE.into()];
 
                         // get the type of device
                         keyboard.send(&keyboard.device_type())?;
                         let bytes_read = keyboard.recieve(&mut buf)?;
-                        if bytes_read == 0 {
-                            return Err(anyhow!(
-                                "Unable to read from device to validate mappings. Please use -p option instead to specify your device."
-                            ));
+                        if bytes_read > 0 {
+                            let device_info = Decoder::get_device_info(&buf);
+                            debug!(
+                                "keys: {} encoders: {}",
+                                device_info.num_keys, device_info.num_encoders
+                            );
+                            device_keys = Some((device_info.num_keys, device_info.num_encoders));
                         }
-                        let device_info = Decoder::get_device_info(&buf);
-                        debug!(
-                            "keys: {} encoders: {}",
-                            device_info.num_keys, device_info.num_encoders
-                        );
+                    }
+                    if device_keys.is_none() {
+                        match State::open()?.load(&profile)? {
+                            Some(shadow) => {
+                                eprintln!("Notice - device cannot be read, comparing with the local record of the last programming, not the device");
+                                device_keys = Some((
+                                    shadow.device.rows * shadow.device.cols,
+                                    shadow.device.knobs,
+                                ));
+                            }
+                            None if profile.read_config => {
+                                return Err(anyhow!(
+                                    "Unable to read from device to validate mappings. Please use -p option instead to specify your device."
+                                ));
+                            }
+                            None => (),
+                        }
+                    }
 
+                    // read the config for buttons/knobs and validate against file
+                    if let Some((num_keys, num_encoders)) = device_keys {
                         let macropad = Mapping::read(config_file);
-                        if device_info.num_keys != macropad.device.rows * macropad.device.cols {
+                        if num_keys != macropad.device.rows * macropad.device.cols {
                             return Err(anyhow!(
                                 "Number of keys specified in config does not match device"
                             ));
                         }
-                        if device_info.num_encoders != macropad.device.knobs {
+                        if num_encoders != macropad.device.knobs {
                             return Err(anyhow!(
                                 "Number of knobs specified in config does not match device"
                             ));
                         }
                     }
-                    Mapping::validate(config_file, Some(device.2))
+                    Mapping::validate(config_file, Some(&profile))
                         .context("validating configuration file with connected device")?;
-                    println!("config is valid 👌")
+                    println!("config is valid 👌");
+                    validated_profile = Some(profile);
                 } else {
                     return Err(anyhow!(
-                        "Unable to find connected device with vendor id: 0x{:02x}",
-                        consts::VENDOR_ID
+                        "Unable to find connected device with vendor id: 0x{vendor_id:02x}"
                     ));
                 }
             } else if let Some(pid) = product_id {
                 debug!("validating with supplied product id 0x{pid:02x}");
-                Mapping::validate(config_file, Some(*pid))
+                let profile = profiles.resolve(vendor_id, *pid, driver)?;
+                Mapping::validate(config_file, Some(&profile))
                     .context("validating configuration file against specified product id")?;
-                println!("config is valid 👌")
+                println!("config is valid 👌");
+                validated_profile = Some(profile);
             } else {
                 // load and validate mapping
                 println!("validating general ron formatting - unable to do more granular checking; use -p option to check against device");
@@ -126,21 +192,116 @@ fn main() -> Result<()> {
                     .context("generic validation of configuration file")?;
                 println!("config is valid 👌")
             }
+
+            if *verbose {
+                Mapping::describe(&Mapping::read(config_file));
+            }
+            match view {
+                Some(ViewFormat::Grid) => show_grid(
+                    &Mapping::read(config_file),
+                    validated_profile.as_ref(),
+                    *key_numbers,
+                )?,
+                Some(ViewFormat::Ron) => Mapping::print(Mapping::read(config_file)),
+                None => (),
+            }
+        }
+
+        Command::Edit {
+            config_file,
+            product_id,
+        } => {
+            let profile = product_id
+                .map(|pid| profiles.resolve(vendor_id, pid, driver))
+                .transpose()?;
+            Editor::new(
+                Mapping::read(config_file),
+                config_file,
+                profile,
+                Some(Box::new(|config: &Macropad| {
+                    program(&options, &profiles, config)
+                })),
+            )
+            .run()?;
+        }
+
+        Command::Describe { config_file } => {
+            Mapping::describe(&Mapping::read(config_file));
         }
 
-        Command::Program { config_file } => {
+        Command::Show {
+            config_file,
+            product_id,
+            key_numbers,
+        } => {
+            let profile = product_id
+                .map(|pid| profiles.resolve(vendor_id, pid, driver))
+                .transpose()?;
+            show_grid(&Mapping::read(config_file), profile.as_ref(), *key_numbers)?;
+        }
+
+        Command::ExportSheet {
+            config_file,
+            format,
+            output,
+        } => {
             let config = Mapping::read(config_file);
-            let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
-            keyboard.program(&config).context("programming macropad")?;
-            println!("successfully programmed device");
+            match format {
+                SheetFormat::Svg => {
+                    for i in 0..config.layers.len() {
+                        let file = format!("{output}-layer{}.svg", i + 1);
+                        std::fs::write(&file, Sheet::svg(&config, i))
+                            .with_context(|| format!("writing {file}"))?;
+                        println!("wrote {file}");
+                    }
+                }
+                SheetFormat::Html => {
+                    let file = format!("{output}.html");
+                    std::fs::write(&file, Sheet::html(&config))
+                        .with_context(|| format!("writing {file}"))?;
+                    println!("wrote {file}");
+                }
+            }
+        }
+
+        Command::Program {
+            config_file,
+            dry_run,
+            product_id,
+        } => {
+            let config = Mapping::read(config_file);
+            if *dry_run {
+                let pid = product_id.expect("product id is required with --dry-run");
+                let profile = profiles.resolve(vendor_id, pid, driver)?;
+                let mut keyboard =
+                    keyboard::new_keyboard(&profile, Some(Box::new(DryRun::default())))?;
+                keyboard.program(&config)?;
+                println!("dry run - nothing was sent to the device");
+            } else {
+                program(&options, &profiles, &config)?;
+                println!("successfully programmed device");
+            }
         }
 
         Command::Led(LedCommand {
-            index,
+            mode,
             layer,
             led_color,
+            list,
         }) => {
-            let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
+            if *list {
+                let pid = match options.devel_options.product_id {
+                    Some(pid) => pid,
+                    None => find_device(&profiles, vendor_id, None, driver)?.2,
+                };
+                show_led_modes(&profiles.resolve(vendor_id, pid, driver)?);
+                return Ok(());
+            }
+
+            let mut keyboard = open_keyboard(&options, &profiles).context("opening keyboard")?;
+            let mode = keyboard
+                .profile()
+                .led_mode(mode.as_ref().expect("mode is required without --list"))?;
 
             // color is not supported on 0x8890 so don't require one to be passed
             let color = if led_color.is_some() {
@@ -149,17 +310,320 @@ fn main() -> Result<()> {
                 LedColor::Red
             };
             keyboard
-                .set_led(*index, *layer, color)
+                .set_led(mode, *layer, color)
                 .context("programming LED on macropad")?;
         }
 
-        Command::Read { layer } => {
+        Command::Read {
+            layer,
+            view,
+            key_numbers,
+        } => {
             debug!("dev options: {:?}", options.devel_options);
-            let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
-            let macropad_config = keyboard
-                .read_macropad_config(layer)
-                .context("reading macropad configuration")?;
-            Mapping::print(macropad_config);
+            let mut keyboard = open_keyboard(&options, &profiles).context("opening keyboard")?;
+            let macropad_config = match keyboard.read_macropad_config(layer) {
+                Ok(config) => config,
+                Err(e) => {
+                    debug!("reading from device failed: {e:?}");
+                    let Some(mut shadow) = State::open()?.load(keyboard.profile())? else {
+                        return Err(e.context("reading macropad configuration"));
+                    };
+                    eprintln!("Notice - device cannot be read, showing the local record of the last programming, not the device");
+                    // like reading the device, the other layers are left empty
+                    if *layer > 0 {
+                        ensure!(
+                            usize::from(*layer) <= shadow.layers.len(),
+                            "layer {layer} is not in the local record of the last programming"
+                        );
+                        let device = &shadow.device;
+                        let empty = Layer::new(device.rows, device.cols, device.knobs);
+                        for (i, l) in shadow.layers.iter_mut().enumerate() {
+                            if i + 1 != usize::from(*layer) {
+                                *l = empty.clone();
+                            }
+                        }
+                    }
+                    shadow
+                }
+            };
+            match view {
+                ViewFormat::Ron => Mapping::print(macropad_config),
+                ViewFormat::Grid => View::print_grid(
+                    &macropad_config,
+                    key_numbers.then_some(keyboard.as_ref() as &dyn Configuration),
+                )?,
+            }
+        }
+
+        Command::ImportCapture {
+            file,
+            product_id,
+            output,
+        } => {
+            let import = Capture::read(Path::new(file))?.import(
+                &profiles,
+                vendor_id,
+                *product_id,
+                driver,
+            )?;
+            eprintln!(
+                "decoded {} programming messages for {}",
+                import.packets, import.profile.name
+            );
+            for note in &import.notes {
+                eprintln!("Notice - {note}");
+            }
+            match output {
+                Some(output) => {
+                    Mapping::write(&import.macropad, output)?;
+                    println!("wrote {output}");
+                }
+                None => Mapping::print(import.macropad),
+            }
+        }
+
+        Command::Monitor { config_file } => {
+            let macropad = if Path::new(config_file).exists() {
+                let data = std::fs::read_to_string(config_file)
+                    .with_context(|| format!("reading {config_file}"))?;
+                Some(Mapping::parse(&data).with_context(|| format!("parsing {config_file}"))?)
+            } else {
+                eprintln!(
+                    "Notice - {config_file} not found, key presses are not matched to bindings"
+                );
+                None
+            };
+            let (device, _desc, pid) = find_device(
+                &profiles,
+                vendor_id,
+                options.devel_options.product_id,
+                driver,
+            )
+            .context("find USB device")?;
+            let profile = profiles.resolve(vendor_id, pid, driver)?;
+            let config = keyboard::new_keyboard(&profile, None)?;
+            Monitor::new(macropad).run(
+                &device,
+                options.usb_settings(),
+                Some(config.as_ref() as &dyn Configuration),
+            )?;
+        }
+
+        Command::Convert {
+            config_file,
+            to,
+            strategy,
+            product_id,
+            output,
+        } => {
+            let profile = product_id
+                .map(|pid| profiles.resolve(vendor_id, pid, driver))
+                .transpose()?;
+            let max_layers = profile.as_ref().map_or(consts::NUM_LAYERS, |p| p.layers);
+            let conversion =
+                Convert::convert(Mapping::read(config_file), *to, *strategy, max_layers)?;
+            for binding in &conversion.dropped {
+                eprintln!("Notice - dropped {binding}");
+            }
+            if conversion.added_layers > 0 {
+                eprintln!(
+                    "Notice - added {} layers for the bindings that did not fit",
+                    conversion.added_layers
+                );
+            }
+            // new buttons are left empty and must be mapped before programming. Warnings
+            // go to stderr, stdout may be the converted configuration
+            let validation = Mapping::validate_config(&conversion.macropad, profile.as_ref())
+                .context("validating converted configuration");
+            for warning in validation.iter().flatten() {
+                eprintln!("Warning - {warning}");
+            }
+            match output {
+                Some(output) => {
+                    Mapping::write(&conversion.macropad, output)?;
+                    println!("wrote {output}");
+                }
+                None => Mapping::print(conversion.macropad),
+            }
+            validation?;
+        }
+
+        Command::Fmt { files, check } => {
+            let mut unformatted = Vec::new();
+            for file in files {
+                let data =
+                    std::fs::read_to_string(file).with_context(|| format!("reading {file}"))?;
+                let formatted =
+                    Format::format(&data).with_context(|| format!("formatting {file}"))?;
+                if formatted == data {
+                    continue;
+                }
+                if *check {
+                    println!("{file} is not formatted");
+                    unformatted.push(file);
+                } else {
+                    std::fs::write(file, formatted).with_context(|| format!("writing {file}"))?;
+                    println!("formatted {file}");
+                }
+            }
+            ensure!(
+                unformatted.is_empty(),
+                "{} files are not formatted, run macropad-tool fmt",
+                unformatted.len()
+            );
+        }
+
+        Command::Calibrate {
+            config_file,
+            rows,
+            cols,
+            knobs,
+        } => {
+            let mut config = config_file.as_deref().map(Mapping::read);
+            let (device, desc, pid) = find_device(
+                &profiles,
+                vendor_id,
+                options.devel_options.product_id,
+                driver,
+            )
+            .context("find USB device")?;
+            let mut keyboard = open_device(&options, &profiles, &device, &desc, pid)
+                .context("opening keyboard")?;
+
+            // size of the pad from the arguments, the configuration or the device
+            let size = match (&config, *rows, *cols, *knobs) {
+                (_, Some(rows), Some(cols), Some(knobs)) => (rows, cols, knobs),
+                (Some(config), ..) => (config.device.rows, config.device.cols, config.device.knobs),
+                (None, ..) if keyboard.profile().read_config => {
+                    Calibrate::read_size(keyboard.as_mut())?
+                }
+                (None, ..) => {
+                    return Err(anyhow!(
+                        "Unable to read the size of the device. Please use --rows, --cols and --knobs or -c"
+                    ))
+                }
+            };
+            let size = (
+                rows.unwrap_or(size.0),
+                cols.unwrap_or(size.1),
+                knobs.unwrap_or(size.2),
+            );
+            let calibrate = Calibrate::new(size.0, size.1, size.2)?;
+            if let (Some(config), Some(config_file)) = (&config, config_file) {
+                ensure!(
+                    (config.device.rows, config.device.cols, config.device.knobs) == size,
+                    "the size of the device in {config_file} does not match the calibrated size"
+                );
+            }
+            // configuration put back when calibrating fails
+            let previous = match &config {
+                Some(config) => Some(config.clone()),
+                None => State::open()?.load(keyboard.profile())?,
+            };
+
+            // program a unique key for every key and knob action, then read them back
+            keyboard
+                .program(&calibrate.macropad())
+                .context("programming calibration keys")?;
+            drop(keyboard);
+            let result = HidReader::open(&device, options.usb_settings())
+                .and_then(|mut reader| calibrate.record(&mut reader))
+                .and_then(|pressed| {
+                    let profile = profiles.resolve(vendor_id, pid, driver)?;
+                    let driver_config = keyboard::new_keyboard(&profile, None)?;
+                    let calibration = calibrate.calibration(driver_config.as_ref(), &pressed)?;
+                    if let Some(knob_order) = &calibration.knob_order {
+                        eprintln!(
+                            "Notice - knobs from top to bottom, left to right are numbered {knob_order:?} in the configuration"
+                        );
+                    }
+                    match (config.as_mut(), config_file) {
+                        (Some(config), Some(config_file)) => {
+                            config.device.orientation = calibration.device.orientation;
+                            config.device.key_map = calibration.device.key_map;
+                            Mapping::update_device(&config.device, config_file)?;
+                            println!("updated the device section of {config_file}");
+                            program(&options, &profiles, config)?;
+                            println!("successfully programmed device");
+                        }
+                        _ => {
+                            println!("{}", calibration.device_ron());
+                            println!("use this device section in your configuration and program it to replace the calibration keys");
+                        }
+                    }
+                    Ok(())
+                });
+
+            // do not leave the calibration keys on the device
+            if let Err(e) = result {
+                match Calibrate::restore(previous.as_ref(), |previous| {
+                    program(&options, &profiles, previous)
+                }) {
+                    Some(Ok(())) => {
+                        eprintln!("Notice - programmed the previous configuration again")
+                    }
+                    Some(Err(e)) => eprintln!(
+                        "Warning - unable to program the previous configuration, the device keeps the calibration keys - {e:#}"
+                    ),
+                    None => eprintln!(
+                        "Warning - no previous configuration, the device keeps the calibration keys until it is programmed"
+                    ),
+                }
+                return Err(e);
+            }
+        }
+
+        Command::Doctor => Doctor::run(&options, &profiles)?,
+
+        Command::UdevRules {
+            mode,
+            group,
+            uaccess,
+            install,
+        } => {
+            let access = if *uaccess {
+                Access::Uaccess
+            } else {
+                Access::Mode {
+                    mode: mode.clone(),
+                    group: group.clone(),
+                }
+            };
+            let rules = UdevRules::generate(&profiles, &access)?;
+            match install {
+                Some(dir) => {
+                    let file = UdevRules::install(&rules, Path::new(dir), &access)?;
+                    println!("wrote {}", file.display());
+                    println!("reload the rules with: sudo udevadm control --reload && sudo udevadm trigger");
+                }
+                None => print!("{rules}"),
+            }
+        }
+
+        Command::Watch {
+            config_file,
+            port,
+            debounce,
+        } => {
+            let product_id = options.devel_options.product_id;
+            let watch = Watch::new(vendor_id, port.clone(), Duration::from_secs(*debounce));
+            watch.run(
+                |pid| match product_id {
+                    Some(product_id) => pid == product_id,
+                    None => driver.is_some() || profiles.find(vendor_id, pid).is_some(),
+                },
+                |device, desc| {
+                    // read the configuration again for every device, it may have changed
+                    let data = std::fs::read_to_string(config_file)
+                        .with_context(|| format!("reading {config_file}"))?;
+                    let config =
+                        Mapping::parse(&data).with_context(|| format!("parsing {config_file}"))?;
+                    let mut keyboard =
+                        open_device(&options, &profiles, &device, &desc, desc.product_id())
+                            .context("opening keyboard")?;
+                    program_keyboard(&options, keyboard.as_mut(), &config)
+                },
+            )?;
         }
     }
 
@@ -213,7 +677,7 @@ pub fn find_interface_and_endpoint(
         let descriptors = intf_desc.endpoint_descriptors();
         for endpoint in descriptors {
             // check packet size
-            if endpoint.max_packet_size() != (consts::PACKET_SIZE - 1).try_into()? {
+            if endpoint.max_packet_size() != u16::try_from(consts::PACKET_SIZE - 1)? {
                 continue;
             }
 
@@ -256,14 +720,86 @@ pub fn find_interface_and_endpoint(
     Err(anyhow!("No valid interface/endpoint combination found!"))
 }
 
-fn open_keyboard(options: &Options) -> Result<Box<dyn Keyboard>> {
+/// Programs the connected device and keeps a local record of the configuration
+/// for devices that cannot be read back
+///
+/// #Arguments
+/// `options` - command line options
+/// `profiles` - device profiles
+/// `config` - configuration to be programmed
+///
+fn program(options: &Options, profiles: &Profiles, config: &Macropad) -> Result<()> {
+    let mut keyboard = open_keyboard(options, profiles).context("opening keyboard")?;
+    program_keyboard(options, keyboard.as_mut(), config)
+}
+
+/// Programs the configuration into the opened keyboard and keeps a local record of it
+///
+/// #Arguments
+/// `options` - command line options
+/// `keyboard` - opened keyboard
+/// `config` - configuration to be programmed
+///
+fn program_keyboard(
+    options: &Options,
+    keyboard: &mut dyn Keyboard,
+    config: &Macropad,
+) -> Result<()> {
+    keyboard.program(config).context("programming macropad")?;
+    if options.replay.is_some() {
+        // nothing was programmed, keep the record of the real device
+        return Ok(());
+    }
+    if let Err(e) = State::open().and_then(|state| state.save(keyboard.profile(), config)) {
+        eprintln!("Warning - unable to keep a local record of the configuration - {e:#}");
+    }
+    Ok(())
+}
+
+fn open_keyboard(options: &Options, profiles: &Profiles) -> Result<Box<dyn Keyboard>> {
+    if let Some(file) = &options.replay {
+        let session = Session::read(Path::new(file))?;
+        let profile = profiles.resolve(
+            session.vendor_id,
+            session.product_id,
+            options.devel_options.driver,
+        )?;
+        eprintln!(
+            "replaying {} packets of 0x{:04x}:0x{:04x} from {file}",
+            session.packets.len(),
+            session.vendor_id,
+            session.product_id
+        );
+        return keyboard::new_keyboard(&profile, Some(Box::new(Replay::new(session))));
+    }
+
     // Find USB device based on the product id
     let (device, desc, id_product) = find_device(
+        profiles,
         options.devel_options.vendor_id,
         options.devel_options.product_id,
+        options.devel_options.driver,
     )
     .context("find USB device")?;
+    open_device(options, profiles, &device, &desc, id_product)
+}
 
+/// Opens the USB device and returns its driver
+///
+/// #Arguments
+/// `options` - command line options
+/// `profiles` - device profiles
+/// `device` - usb device
+/// `desc` - device descriptor of the device
+/// `id_product` - product id of the device
+///
+fn open_device(
+    options: &Options,
+    profiles: &Profiles,
+    device: &Device<Context>,
+    desc: &DeviceDescriptor,
+    id_product: u16,
+) -> Result<Box<dyn Keyboard>> {
     ensure!(
         desc.num_configurations() == 1,
         "only one device configuration is expected"
@@ -271,7 +807,7 @@ fn open_keyboard(options: &Options) -> Result<Box<dyn Keyboard>> {
 
     // Find correct endpoint
     let (intf_num, endpt_addr_out, endpt_addr_in) = find_interface_and_endpoint(
-        &device,
+        device,
         options.devel_options.interface_number,
         options.devel_options.out_endpoint_address,
         options.devel_options.in_endpoint_address,
@@ -279,23 +815,82 @@ fn open_keyboard(options: &Options) -> Result<Box<dyn Keyboard>> {
 
     // Open device.
     let handle = device.open().context("open USB device")?;
-    let _ = handle.set_auto_detach_kernel_driver(true);
-    handle
-        .claim_interface(intf_num)
-        .context("claim interface")?;
-
-    match id_product {
-        0x8840 | 0x8842 => {
-            k884x::Keyboard884x::new(Some(handle), endpt_addr_out, endpt_addr_in, id_product)
-                .map(|v| Box::new(v) as Box<dyn Keyboard>)
-        }
-        0x8890 => k8890::Keyboard8890::new(Some(handle), endpt_addr_out)
-            .map(|v| Box::new(v) as Box<dyn Keyboard>),
-        _ => unreachable!("This shouldn't happen!"),
+
+    let profile = profiles.resolve(
+        options.devel_options.vendor_id,
+        id_product,
+        options.devel_options.driver,
+    )?;
+    debug!("profile: {profile:?}");
+    let mut transport: Box<dyn Transport> = Box::new(UsbTransport::open(
+        handle,
+        intf_num,
+        endpt_addr_out,
+        endpt_addr_in,
+        options.usb_settings(),
+    )?);
+    if let Some(file) = &options.record {
+        let session = Session::new(profile.vendor_id, profile.product_id);
+        transport = Box::new(Recorder::new(transport, Path::new(file), session));
     }
+    keyboard::new_keyboard(&profile, Some(transport))
 }
 
-pub fn find_device(vid: u16, pid: Option<u16>) -> Result<(Device<Context>, DeviceDescriptor, u16)> {
+/// Prints the LED modes supported by the device
+///
+/// #Arguments
+/// `profile` - profile of the device
+///
+fn show_led_modes(profile: &DeviceProfile) {
+    if profile.led_modes.is_empty() {
+        println!("{} macropad has no LEDs", profile.name);
+        return;
+    }
+    println!("LED modes of {} macropad:", profile.name);
+    for (i, mode) in profile.led_modes.iter().enumerate() {
+        println!("  {i} - {mode}");
+    }
+    if profile.led_color {
+        let colors = LedColor::value_variants()
+            .iter()
+            .filter_map(|c| c.to_possible_value())
+            .map(|v| v.get_name().to_string());
+        println!("colors: {}", colors.format(", "));
+    } else {
+        println!("colors are not supported");
+    }
+    if profile.led_per_layer {
+        println!("LEDs are set per layer");
+    }
+}
+
+/// Prints the Macropad as a grid, optionally with the key numbers of the
+/// driver for the specified device profile
+///
+/// #Arguments
+/// `macropad` - configuration to be displayed
+/// `profile` - device profile used to number the keys
+/// `key_numbers` - overlay the key numbers on the grid
+///
+fn show_grid(
+    macropad: &Macropad,
+    profile: Option<&DeviceProfile>,
+    key_numbers: bool,
+) -> Result<()> {
+    if !key_numbers {
+        return View::print_grid(macropad, None);
+    }
+    let profile = profile.ok_or_else(|| anyhow!("a product id is needed to show key numbers"))?;
+    let driver = keyboard::new_keyboard(profile, None)?;
+    View::print_grid(macropad, Some(driver.as_ref() as &dyn Configuration))
+}
+
+pub fn find_device(
+    profiles: &Profiles,
+    vid: u16,
+    pid: Option<u16>,
+    driver: Option<DriverFamily>,
+) -> Result<(Device<Context>, DeviceDescriptor, u16)> {
     debug!("vid: 0x{vid:02x}");
     if let Some(prod_id) = pid {
         debug!("pid: 0x{prod_id:02x}");
@@ -321,11 +916,13 @@ pub fn find_device(vid: u16, pid: Option<u16>) -> Result<(Device<Context>, Devic
         let product_id = desc.product_id();
 
         if desc.vendor_id() == vid {
-            if let Some(prod_id) = pid {
-                if PRODUCT_IDS.contains(&prod_id) {
-                    found.push((device, desc, product_id));
-                }
-            } else {
+            // a specified product id must match exactly, otherwise take devices we have
+            // a profile for (or any device of the vendor when the driver is forced)
+            let matches = match pid {
+                Some(prod_id) => product_id == prod_id,
+                None => driver.is_some() || profiles.find(vid, product_id).is_some(),
+            };
+            if matches {
                 found.push((device, desc, product_id));
             }
         }
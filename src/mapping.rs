@@ -1,9 +1,9 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use log::debug;
 use serde::{Deserialize, Serialize};
 
 /// Mapping configuration of a macropad
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Macropad {
     /// Device configuration
     pub device: Device,
@@ -26,6 +26,8 @@ impl Macropad {
                 rows,
                 cols,
                 knobs,
+                led: None,
+                key_map: None,
             },
             layers: vec![
                 Layer::new(rows, cols, knobs),
@@ -37,7 +39,7 @@ impl Macropad {
 }
 
 /// Device configuration
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     /// Orientation of device
     pub orientation: Orientation,
@@ -47,15 +49,66 @@ pub struct Device {
     pub cols: u8,
     /// Number of knobs
     pub knobs: u8,
+    /// LED settings for devices that set them once for all layers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub led: Option<Led>,
+    /// Key numbers of the buttons by row, for pads whose numbering no orientation
+    /// matches (replaces the orientation, written by calibrate)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_map: Option<Vec<Vec<u8>>>,
+}
+
+impl Device {
+    /// Checks that the key map, if there is one, has a key number for every
+    /// button and uses each key number once
+    ///
+    pub fn check_key_map(&self) -> Result<()> {
+        let Some(key_map) = &self.key_map else {
+            return Ok(());
+        };
+        if key_map.len() != usize::from(self.rows)
+            || key_map
+                .iter()
+                .any(|row| row.len() != usize::from(self.cols))
+        {
+            return Err(anyhow!(
+                "key map must have {} rows of {} key numbers",
+                self.rows,
+                self.cols
+            ));
+        }
+        let mut numbers: Vec<u8> = key_map.iter().flatten().copied().collect();
+        numbers.sort();
+        if numbers != (1..=self.rows * self.cols).collect::<Vec<u8>>() {
+            return Err(anyhow!(
+                "key map must use each key number from 1 to {} once",
+                self.rows * self.cols
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// Layer configuration
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Layer {
     /// Key mappings
     pub buttons: Vec<Vec<Button>>,
     /// Rotary encoder mappings
     pub knobs: Vec<Knob>,
+    /// LED settings for devices that set them per layer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub led: Option<Led>,
+}
+
+/// LED settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Led {
+    /// Preset mode of the LEDs, by number or name (see `led --list`)
+    pub mode: LedMode,
+    /// Color to use for the mode, on devices supporting colors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<LedColor>,
 }
 
 impl Layer {
@@ -80,7 +133,11 @@ impl Layer {
                 cw: Button::new(),
             });
         }
-        Self { buttons, knobs }
+        Self {
+            buttons,
+            knobs,
+            led: None,
+        }
     }
 }
 
@@ -91,6 +148,12 @@ pub struct Button {
     pub delay: u16,
     /// Mapping for the button
     pub mapping: String,
+    /// Short human readable name for the binding (not programmed to the device)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    /// Longer explanation of what the binding does (not programmed to the device)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
 }
 
 impl Button {
@@ -100,12 +163,14 @@ impl Button {
         Self {
             delay: 0,
             mapping: String::new(),
+            label: String::new(),
+            description: String::new(),
         }
     }
 }
 
 /// Mapping for a knob
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Knob {
     /// Counter-Clockwise turn
     pub ccw: Button,
@@ -115,14 +180,19 @@ pub struct Knob {
     pub cw: Button,
 }
 
-use ron::de::from_reader;
+use ron::extensions::Extensions;
 use ron::ser::{to_string_pretty, PrettyConfig};
 use std::fs::File;
 use std::str::FromStr;
 
 use crate::config::Orientation;
 use crate::consts;
-use crate::keyboard::{MediaCode, Modifier, WellKnownCode};
+use crate::format::Format;
+use crate::keyboard::{LedColor, LedMode, MediaCode, Modifier, MouseEvent, WellKnownCode};
+use crate::profile::{DeviceProfile, KeyClass};
+
+use itertools::Itertools as _;
+use strum::EnumMessage as _;
 
 pub struct Mapping {}
 
@@ -135,7 +205,7 @@ impl Mapping {
     pub fn read(cfg_file: &str) -> Macropad {
         debug!("configuration file: {cfg_file}");
         let f = File::open(cfg_file).expect("Failed opening file");
-        let config: Macropad = match from_reader(f) {
+        let config: Macropad = match Self::ron_options().from_reader(f) {
             Ok(x) => x,
             Err(e) => {
                 println!("Failed to load config: {e}");
@@ -145,56 +215,243 @@ impl Mapping {
         config
     }
 
+    /// Returns the Macropad parsed from a configuration in ron format
+    ///
+    /// #Arguments
+    /// `data` - configuration in ron format
+    ///
+    pub fn parse(data: &str) -> Result<Macropad> {
+        Ok(Self::ron_options().from_str(data)?)
+    }
+
+    /// Optional values (like `led`) can be written without `Some(...)`
+    fn ron_options() -> ron::Options {
+        ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME)
+    }
+
     /// Prints the Macropad to stdout
     ///
     /// #Arguments
     /// `config` - macropad to be printed
     ///
     pub fn print(config: Macropad) {
+        println!("{}", Self::to_ron(&config));
+    }
+
+    /// Writes the Macropad to the specified configuration file
+    ///
+    /// #Arguments
+    /// `config` - macropad to be written
+    /// `cfg_file` - configuration file to write to
+    ///
+    pub fn write(config: &Macropad, cfg_file: &str) -> Result<()> {
+        debug!("writing configuration file: {cfg_file}");
+        std::fs::write(cfg_file, Self::to_ron(config) + "\n")?;
+        Ok(())
+    }
+
+    /// Writes the Macropad to the specified configuration file, keeping the comments
+    /// of the file it replaces
+    ///
+    /// #Arguments
+    /// `config` - macropad to be written
+    /// `cfg_file` - configuration file to update
+    ///
+    pub fn update(config: &Macropad, cfg_file: &str) -> Result<()> {
+        let original = match std::fs::read_to_string(cfg_file) {
+            Ok(original) => original,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Self::write(config, cfg_file)
+            }
+            Err(e) => return Err(e).with_context(|| format!("reading {cfg_file}")),
+        };
+        debug!("updating configuration file: {cfg_file}");
+        let updated = Format::update(&original, config)
+            .with_context(|| format!("keeping the comments of {cfg_file}"))?;
+        std::fs::write(cfg_file, updated)?;
+        Ok(())
+    }
+
+    /// Replaces the device section of the specified configuration file, leaving the
+    /// rest of the file as it is
+    ///
+    /// #Arguments
+    /// `device` - device section to write
+    /// `cfg_file` - configuration file to update
+    ///
+    pub fn update_device(device: &Device, cfg_file: &str) -> Result<()> {
+        debug!("updating the device section of {cfg_file}");
+        let original =
+            std::fs::read_to_string(cfg_file).with_context(|| format!("reading {cfg_file}"))?;
+        let updated = Format::replace_device(&original, device)
+            .with_context(|| format!("replacing the device section of {cfg_file}"))?;
+        std::fs::write(cfg_file, updated)?;
+        Ok(())
+    }
+
+    pub(crate) fn to_ron(config: &Macropad) -> String {
         let pretty = PrettyConfig::new()
             .depth_limit(4)
             .separate_tuple_members(true)
             .enumerate_arrays(false);
 
-        let s = to_string_pretty(&config, pretty).expect("Serialization failed");
-        println!("{s}");
+        to_string_pretty(config, pretty).expect("Serialization failed")
+    }
+
+    /// Prints every binding of the Macropad along with its label and description
+    ///
+    /// #Arguments
+    /// `config` - macropad to be described
+    ///
+    pub fn describe(config: &Macropad) {
+        for line in Self::describe_lines(config) {
+            println!("{line}");
+        }
+    }
+
+    /// Returns one line per binding (buttons first, then knobs) for each layer
+    ///
+    /// #Arguments
+    /// `config` - macropad to be described
+    ///
+    fn describe_lines(config: &Macropad) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (i, layer) in config.layers.iter().enumerate() {
+            lines.push(format!("layer {}", i + 1));
+            for (j, row) in layer.buttons.iter().enumerate() {
+                for (k, btn) in row.iter().enumerate() {
+                    lines.push(Self::describe_button(
+                        &format!("row {} button {}", j + 1, k + 1),
+                        btn,
+                    ));
+                }
+            }
+            for (k, knob) in layer.knobs.iter().enumerate() {
+                for (action, btn) in [("ccw", &knob.ccw), ("press", &knob.press), ("cw", &knob.cw)]
+                {
+                    lines.push(Self::describe_button(
+                        &format!("knob {} {action}", k + 1),
+                        btn,
+                    ));
+                }
+            }
+        }
+        lines
+    }
+
+    /// Returns a human friendly version of a mapping string, e.g. "ctrl-shift-v"
+    /// becomes "Ctrl+Shift+V" and "ctrl-a,ctrl-s" becomes "Ctrl+A, Ctrl+S"
+    ///
+    /// #Arguments
+    /// `mapping` - mapping string as found in the configuration file
+    ///
+    pub fn pretty(mapping: &str) -> String {
+        if mapping.is_empty() {
+            return String::new();
+        }
+        mapping
+            .split(',')
+            .map(|chord| {
+                chord
+                    .split('-')
+                    .map(Self::pretty_key)
+                    .collect::<Vec<_>>()
+                    .join("+")
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 
-    /// Validates the configuration against the specified product ID. If the product ID
+    fn pretty_key(key: &str) -> String {
+        if let Ok(m) = Modifier::from_str(key) {
+            match m {
+                Modifier::RightCtrl => "RCtrl".to_string(),
+                Modifier::RightShift => "RShift".to_string(),
+                Modifier::RightAlt => "RAlt".to_string(),
+                Modifier::RightWin => "RWin".to_string(),
+                _ => format!("{m:?}"),
+            }
+        } else if let Ok(w) = WellKnownCode::from_str(key) {
+            let name = w.to_string();
+            if name.len() == 1 {
+                name.to_uppercase()
+            } else {
+                format!("{w:?}")
+            }
+        } else if let Ok(mc) = MediaCode::from_str(key) {
+            format!("{mc:?}")
+        } else {
+            match key.to_lowercase().as_str() {
+                "click" => "Click".to_string(),
+                "rclick" => "RClick".to_string(),
+                "mclick" => "MClick".to_string(),
+                "wheelup" => "WheelUp".to_string(),
+                "wheeldown" => "WheelDown".to_string(),
+                _ => key.to_string(),
+            }
+        }
+    }
+
+    fn describe_button(position: &str, btn: &Button) -> String {
+        let mut line = format!("  {position}: {}", btn.mapping);
+        if !btn.label.is_empty() {
+            line += &format!(" [{}]", btn.label);
+        }
+        if !btn.description.is_empty() {
+            line += &format!(" - {}", btn.description);
+        }
+        line
+    }
+
+    /// Validates the configuration against the specified device profile. If the profile
     ///  is not specified, does general validation. Returns `Result<Ok()>` on success; Err
     /// otherwise
     ///
     /// #Arguments
     /// `cfg_file` - configuration file to validate
-    /// `pid` - Optional product id to validate against
-    ///
-    pub fn validate(cfg_file: &str, pid: Option<u16>) -> anyhow::Result<()> {
-        // get the maximum number a key can be programmed for
-        let mut max_programmable_keys = 0xff;
-        if let Some(max) = pid {
-            match max {
-                0x8840 | 0x8842 => max_programmable_keys = consts::MAX_KEY_PRESSES_884X,
-                0x8890 => max_programmable_keys = consts::MAX_KEY_PRESSES_8890,
-                _ => {
-                    let err_msg = format!("Unknown product id 0x{:02x}", pid.unwrap());
-                    return Err(anyhow!(err_msg));
-                }
-            }
+    /// `profile` - Optional device profile to validate against
+    ///
+    pub fn validate(cfg_file: &str, profile: Option<&DeviceProfile>) -> anyhow::Result<()> {
+        for warning in Self::validate_config(&Self::read(cfg_file), profile)? {
+            eprintln!("Warning - {warning}");
         }
-        debug!("max_programmable_keys: {max_programmable_keys}");
-        debug!("pid: {pid:?}");
+        Ok(())
+    }
+
+    /// Validates the Macropad against the specified device profile. If the profile
+    /// is not specified, does general validation. Returns the warnings about settings
+    /// the device ignores on success; Err otherwise
+    ///
+    /// #Arguments
+    /// `cfg` - configuration to validate
+    /// `profile` - Optional device profile to validate against
+    ///
+    pub fn validate_config(
+        cfg: &Macropad,
+        profile: Option<&DeviceProfile>,
+    ) -> anyhow::Result<Vec<String>> {
+        debug!("profile: {profile:?}");
+        let mut warnings = Vec::new();
 
         // check layers
-        let cfg = Self::read(cfg_file);
+        let max_layers = profile.map(|p| p.layers).unwrap_or(consts::NUM_LAYERS);
+        if cfg.layers.is_empty() || cfg.layers.len() > max_layers.into() {
+            return Err(anyhow!(
+                "number of layers must be > 0 and < {}",
+                max_layers + 1
+            ));
+        }
 
-        if cfg.layers.is_empty() || cfg.layers.len() > 3 {
-            return Err(anyhow!("number of layers must be > 0 and < 4"));
+        // check LED settings
+        if let Some(p) = profile {
+            warnings = Self::validate_led(cfg, p)?;
         }
+        cfg.device.check_key_map()?;
 
         // check rows/cols/knobs
         for (i, layer) in cfg.layers.iter().enumerate() {
             // row check
-            if layer.buttons.len() != cfg.device.rows.into() {
+            if layer.buttons.len() != usize::from(cfg.device.rows) {
                 return Err(anyhow!(
                     "number of rows mismatch at layer {}. Expected {} rows found {}",
                     i + 1,
@@ -205,7 +462,7 @@ impl Mapping {
 
             // column check
             for (j, btn_mapping) in layer.buttons.iter().enumerate() {
-                if btn_mapping.len() != cfg.device.cols.into() {
+                if btn_mapping.len() != usize::from(cfg.device.cols) {
                     return Err(anyhow!(
                         "number of colums mismatch at layer {} row {}. Expected {} columns found {}",
                         i + 1,
@@ -217,22 +474,16 @@ impl Mapping {
 
                 // check the individual button
                 for (k, btn) in btn_mapping.iter().enumerate() {
-                    let retval = Self::validate_key_mapping(btn, max_programmable_keys, pid);
-                    if retval.is_err() {
-                        return Err(anyhow!(
-                            "{} -- '{}' at layer {} row {} button {}",
-                            retval.err().unwrap(),
-                            btn.mapping,
-                            i + 1,
-                            j + 1,
-                            k + 1
-                        ));
+                    let place = format!("layer {} row {} button {}", i + 1, j + 1, k + 1);
+                    match Self::validate_key_mapping(btn, profile) {
+                        Ok(w) => warnings.extend(w.into_iter().map(|w| format!("{w} at {place}"))),
+                        Err(e) => return Err(anyhow!("{e} -- '{}' at {place}", btn.mapping)),
                     }
                 }
             }
 
             // knob check
-            if layer.knobs.len() != cfg.device.knobs.into() {
+            if layer.knobs.len() != usize::from(cfg.device.knobs) {
                 return Err(anyhow!(
                     "number of knobs mismatch at layer {}. Expected {} knobs found {}",
                     i + 1,
@@ -243,44 +494,68 @@ impl Mapping {
 
             // knob button mapping
             for (k, knob) in layer.knobs.iter().enumerate() {
-                let retval = Self::validate_key_mapping(&knob.ccw, max_programmable_keys, pid);
-                if retval.is_err() {
-                    return Err(anyhow!(
-                        "{} - key '{}' at layer {} knob {} in ccw",
-                        retval.err().unwrap(),
-                        &knob.ccw.mapping,
-                        i + 1,
-                        k + 1
-                    ));
-                }
-                let retval = Self::validate_key_mapping(&knob.press, max_programmable_keys, pid);
-                if retval.is_err() {
-                    return Err(anyhow!(
-                        "{} - key '{}' at layer {} knob {} in press",
-                        retval.err().unwrap(),
-                        &knob.press.mapping,
-                        i + 1,
-                        k + 1
-                    ));
-                }
-                let retval = Self::validate_key_mapping(&knob.cw, max_programmable_keys, pid);
-                if retval.is_err() {
-                    return Err(anyhow!(
-                        "{} - key '{}' at layer {} knob {} in cw",
-                        retval.err().unwrap(),
-                        &knob.cw.mapping,
-                        i + 1,
-                        k + 1
-                    ));
+                for (action, btn) in [("ccw", &knob.ccw), ("press", &knob.press), ("cw", &knob.cw)]
+                {
+                    let place = format!("layer {} knob {} in {action}", i + 1, k + 1);
+                    match Self::validate_key_mapping(btn, profile) {
+                        Ok(w) => warnings.extend(w.into_iter().map(|w| format!("{w} at {place}"))),
+                        Err(e) => return Err(anyhow!("{e} - key '{}' at {place}", btn.mapping)),
+                    }
                 }
             }
         }
 
-        Ok(())
+        Ok(warnings)
+    }
+
+    fn validate_led(cfg: &Macropad, profile: &DeviceProfile) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        let layer_leds = cfg.layers.iter().enumerate().filter_map(|(i, layer)| {
+            layer
+                .led
+                .as_ref()
+                .map(|led| (format!("layer {}", i + 1), led))
+        });
+        let device_led = cfg
+            .device
+            .led
+            .as_ref()
+            .map(|led| ("device".to_string(), led));
+
+        for (place, led) in layer_leds.chain(device_led) {
+            if profile.led_modes.is_empty() {
+                return Err(anyhow!("{} macropad has no LEDs", profile.name));
+            }
+            let on_device = place == "device";
+            if profile.led_per_layer && on_device {
+                return Err(anyhow!(
+                    "{} macropad sets LEDs per layer - move led from device to the layers",
+                    profile.name
+                ));
+            }
+            if !profile.led_per_layer && !on_device {
+                return Err(anyhow!(
+                    "{} macropad sets LEDs for the whole device - move led from {place} to device",
+                    profile.name
+                ));
+            }
+            profile
+                .led_mode(&led.mode)
+                .with_context(|| format!("LED at {place}"))?;
+            if led.color.is_some() && !profile.led_color {
+                warnings.push(format!(
+                    "{} devices do not support LED colors - color at {place} will be ignored",
+                    profile.name
+                ));
+            }
+        }
+        Ok(warnings)
     }
 
-    fn validate_key_mapping(btn: &Button, max_size: usize, pid: Option<u16>) -> Result<()> {
+    fn validate_key_mapping(btn: &Button, profile: Option<&DeviceProfile>) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
         // ensure we don't go over max
+        let max_size = profile.map_or(consts::MAX_KEY_PRESSES, |p| p.max_key_presses);
         let keys: Vec<_> = btn.mapping.split(',').collect();
         if keys.len() > max_size {
             return Err(anyhow!(
@@ -290,68 +565,182 @@ impl Mapping {
         }
 
         // check delay
-        if max_size == consts::MAX_KEY_PRESSES_8890 {
-            if btn.delay > 0 {
-                println!(
-                    "Warning - 0x8890 devices do not support the delay feature - delay value [{}] will be ignored", btn.delay
-                );
+        match profile {
+            Some(p) if !p.delay => {
+                if btn.delay > 0 {
+                    warnings.push(format!(
+                        "{} devices do not support the delay feature - delay value [{}] will be ignored",
+                        p.name, btn.delay
+                    ));
+                }
+            }
+            _ => {
+                if btn.delay > consts::MAX_DELAY {
+                    return Err(anyhow!(
+                        "delay value [{}] must be between 0 and 6000 msec",
+                        btn.delay
+                    ));
+                }
             }
-        } else if btn.delay > consts::MAX_DELAY {
-            return Err(anyhow!(
-                "delay value [{}] must be between 0 and 6000 msec",
-                btn.delay
-            ));
         }
 
+        Self::check_key_classes(&btn.mapping, profile)?;
+
         // check individual keys
         for (i, k) in keys.iter().enumerate() {
+            let mouse = MouseEvent::parse(k)?;
             let single_key: Vec<_> = k.split('-').collect();
-            if max_size == consts::MAX_KEY_PRESSES_8890 && i > 0 && single_key.len() > 1 {
-                return Err(anyhow!(
-                    "0x8890 macropad only supports modifier keys on first key in sequence"
-                ));
+            let modified = match mouse {
+                Some(MouseEvent(_, modifier)) => modifier.is_some(),
+                None => single_key.len() > 1,
+            };
+            if let Some(p) = profile {
+                if p.modifiers_first_only && i > 0 && modified {
+                    return Err(anyhow!(
+                        "{} macropad only supports modifier keys on first key in sequence",
+                        p.name
+                    ));
+                }
+            }
+            if mouse.is_some() {
+                // mouse buttons or wheel, with the modifier held
+                let mut classes = vec![KeyClass::Mouse];
+                if modified {
+                    classes.push(KeyClass::Modifier);
+                }
+                if let Some(class) = classes
+                    .into_iter()
+                    .find(|c| profile.is_some_and(|p| !p.supports(*c)))
+                {
+                    return Err(anyhow!("unsupported key - {k} ({class:?} keys)"));
+                }
+                continue;
             }
             for sk in single_key {
                 let da_key = Self::uppercase_first(sk);
                 // could be media, control, or regular key
-                let mut found = false;
-                let mut unsupported = "";
-                for i in 0..4 {
-                    match i {
-                        0 => {
-                            found = Self::is_modifier_key(&da_key);
-                        }
-                        1 => {
-                            found = Self::is_media_key(&da_key);
-                            // 0x8890 does not support keys > 0xff
-                            if pid.is_some() && pid.unwrap() == 0x8890 && found {
-                                unsupported = match da_key.as_str() {
-                                    "Play" | "Previous" | "Next" | "Mute" | "Volumeup"
-                                    | "Volumedown" => "",
-                                    _ => &da_key,
-                                };
-                            }
-                        }
-                        2 => {
-                            found = Self::is_regular_key(&da_key);
-                        }
-                        3 => {
-                            found = Self::is_mouse_action(&da_key);
-                        }
-                        _ => (),
-                    }
-                    if !unsupported.is_empty() {
+                let class = if Self::is_modifier_key(&da_key) {
+                    KeyClass::Modifier
+                } else if let Ok(media) = MediaCode::from_str(&da_key) {
+                    if profile.is_some_and(|p| !p.supports_media(media)) {
                         return Err(anyhow!("unsupported media key"));
                     }
-                    if found {
-                        break;
-                    }
-                }
-                if !found {
+                    KeyClass::Media
+                } else if Self::is_regular_key(&da_key) {
+                    KeyClass::Keyboard
+                } else {
                     return Err(anyhow!("unknown key - {}", sk));
+                };
+                if class != KeyClass::Media && profile.is_some_and(|p| !p.supports(class)) {
+                    return Err(anyhow!("unsupported key - {}", sk));
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Returns the mapping in canonical form: lowercase names, aliases replaced by
+    /// the name of the key and the modifiers of a key press first, in a fixed order.
+    /// Key presses with unknown keys are left as they are
+    ///
+    /// #Arguments
+    /// `mapping` - key presses separated by ','
+    ///
+    pub fn canonical(mapping: &str) -> String {
+        mapping
+            .split(',')
+            .map(|key| {
+                if let Ok(Some(event)) = MouseEvent::parse(key) {
+                    return event.to_string();
+                }
+                let mut modifiers = Vec::new();
+                let mut keys = Vec::new();
+                for part in key.split('-') {
+                    if let Ok(m) = Modifier::from_str(part) {
+                        modifiers.push(m);
+                    } else if let Ok(m) = MediaCode::from_str(part) {
+                        keys.push(m.get_serializations()[0].to_string());
+                    } else if let Ok(w) = WellKnownCode::from_str(part) {
+                        keys.push(w.to_string());
+                    } else {
+                        return key.to_string();
+                    }
                 }
+                modifiers.sort_by_key(|m| *m as u8);
+                modifiers.dedup();
+                modifiers
+                    .iter()
+                    .map(|m| m.get_serializations()[0].to_string())
+                    .chain(keys)
+                    .join("-")
+            })
+            .join(",")
+    }
+
+    /// Returns the class of a key press of a sequence: Keyboard for keys and
+    /// modifiers, Media or Mouse
+    ///
+    /// #Arguments
+    /// `key` - key press, eg. `ctrl-a`
+    ///
+    fn key_class(key: &str) -> KeyClass {
+        if matches!(MouseEvent::parse(key), Ok(Some(_))) {
+            KeyClass::Mouse
+        } else if key
+            .split('-')
+            .any(|k| MediaCode::from_str(&Self::uppercase_first(k)).is_ok())
+        {
+            KeyClass::Media
+        } else {
+            KeyClass::Keyboard
+        }
+    }
+
+    /// Checks that the key presses of the mapping can be sent together. A media key
+    /// is sent without modifiers and, when the driver has one key type per
+    /// sequence, a media key or mouse event must be the only key press
+    ///
+    /// #Arguments
+    /// `mapping` - key presses separated by ','
+    /// `profile` - Optional device profile whose driver sends the mapping
+    ///
+    pub fn check_key_classes(mapping: &str, profile: Option<&DeviceProfile>) -> Result<()> {
+        let keys: Vec<_> = mapping.split(',').collect();
+        for k in &keys {
+            let parts: Vec<_> = k.split('-').collect();
+            if Self::key_class(k) == KeyClass::Media && parts.len() > 1 {
+                let media = parts
+                    .iter()
+                    .find(|p| MediaCode::from_str(&Self::uppercase_first(p)).is_ok())
+                    .unwrap_or(&parts[0]);
+                let lost = parts.iter().filter(|p| *p != media).join("-");
+                return Err(anyhow!(
+                    "media keys are sent on their own - '{lost}' would be lost from '{k}'"
+                ));
             }
         }
+
+        let Some(p) = profile.filter(|p| !p.driver.mixes_key_classes()) else {
+            return Ok(());
+        };
+        if keys.len() < 2 {
+            return Ok(());
+        }
+        if let Some(i) = keys
+            .iter()
+            .position(|k| Self::key_class(k) != KeyClass::Keyboard)
+        {
+            let k = keys[i];
+            let lost = (keys[..i].iter().chain(&keys[i + 1..])).join(",");
+            return Err(anyhow!(
+                "{} macropad programs a button with keyboard keys or a single {} - '{lost}' would be lost from '{mapping}'",
+                p.name,
+                match Self::key_class(k) {
+                    KeyClass::Mouse => "mouse event",
+                    _ => "media key",
+                }
+            ));
+        }
         Ok(())
     }
 
@@ -377,14 +766,6 @@ impl Mapping {
         false
     }
 
-    fn is_media_key(keystr: &str) -> bool {
-        let mk = MediaCode::from_str(keystr);
-        if mk.is_ok() {
-            return true;
-        }
-        false
-    }
-
     fn is_regular_key(keystr: &str) -> bool {
         let rk = WellKnownCode::from_str(keystr);
         if rk.is_ok() {
@@ -392,21 +773,23 @@ impl Mapping {
         }
         false
     }
-
-    fn is_mouse_action(keystr: &str) -> bool {
-        matches!(
-            keystr.to_lowercase().as_str(),
-            "wheelup" | "wheeldown" | "click" | "mclick" | "rclick"
-        )
-    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::mapping::Button;
+    use crate::keyboard::{LedColor, LedMode};
+    use crate::mapping::{Button, Led, Macropad};
+    use crate::profile::{DeviceProfile, KeyClass, Profiles};
     use crate::{consts, mapping::Mapping};
 
+    fn profile(pid: u16) -> DeviceProfile {
+        Profiles::builtin()
+            .get(consts::VENDOR_ID, pid)
+            .unwrap()
+            .clone()
+    }
+
     #[test]
     fn mapping_read() {
         Mapping::read("./mapping.ron");
@@ -417,6 +800,99 @@ mod tests {
         Mapping::print(Mapping::read("./mapping.ron"));
     }
 
+    #[test]
+    fn mapping_labels() {
+        let mut macropad: Macropad = ron::from_str(
+            r#"(
+                device: (orientation: Normal, rows: 1, cols: 1, knobs: 1),
+                layers: [(
+                    buttons: [[(delay: 0, mapping: "ctrl-alt-f3", label: "Console", description: "switch to tty3")]],
+                    knobs: [(ccw: (delay: 0, mapping: "volumedown"), press: (delay: 0, mapping: "mute", label: "Mute"), cw: (delay: 0, mapping: "volumeup"))],
+                )],
+            )"#,
+        )
+        .unwrap();
+        let lines = Mapping::describe_lines(&macropad);
+        assert_eq!(
+            lines[1],
+            "  row 1 button 1: ctrl-alt-f3 [Console] - switch to tty3"
+        );
+        assert_eq!(lines[2], "  knob 1 ccw: volumedown");
+        assert_eq!(lines[3], "  knob 1 press: mute [Mute]");
+
+        // labels survive serialization, unlabeled buttons stay terse
+        macropad.layers[0].knobs[0].cw.description = "louder".to_string();
+        let s = ron::to_string(&macropad).unwrap();
+        assert!(s.contains(r#"label:"Console""#));
+        assert_eq!(s.matches("label:").count(), 2);
+        let reread: Macropad = ron::from_str(&s).unwrap();
+        assert_eq!(reread.layers[0].knobs[0].cw.description, "louder");
+    }
+
+    #[test]
+    fn mapping_led() -> anyhow::Result<()> {
+        let mut macropad = Mapping::parse(
+            r#"(
+                device: (orientation: Normal, rows: 1, cols: 1, knobs: 0),
+                layers: [(
+                    buttons: [[(delay: 0, mapping: "a")]],
+                    knobs: [],
+                    led: (mode: 1, color: Blue),
+                )],
+            )"#,
+        )?;
+        let led = macropad.layers[0].led.clone().expect("layer led");
+        assert_eq!(
+            (led.mode, led.color),
+            (LedMode::Index(1), Some(LedColor::Blue))
+        );
+        Mapping::validate_config(&macropad, Some(&profile(0x8840)))?;
+
+        // the 8890 sets its LEDs for the whole device
+        assert!(Mapping::validate_config(&macropad, Some(&profile(0x8890))).is_err());
+        macropad.device.led = macropad.layers[0].led.take();
+        Mapping::validate_config(&macropad, Some(&profile(0x8890)))?;
+        assert!(Mapping::validate_config(&macropad, Some(&profile(0x8840))).is_err());
+
+        macropad.device.led = Some(Led {
+            mode: LedMode::Index(3),
+            color: None,
+        });
+        assert!(Mapping::validate_config(&macropad, Some(&profile(0x8890))).is_err());
+
+        // modes can be named, names are checked against the device
+        let mut macropad = Mapping::parse(
+            r#"(
+                device: (orientation: Normal, rows: 1, cols: 1, knobs: 0, led: (mode: "cycle")),
+                layers: [(buttons: [[(delay: 0, mapping: "a")]], knobs: [])],
+            )"#,
+        )?;
+        assert_eq!(
+            macropad.device.led.as_ref().map(|l| &l.mode),
+            Some(&LedMode::Name("cycle".to_string()))
+        );
+        Mapping::validate_config(&macropad, Some(&profile(0x8890)))?;
+        macropad.layers[0].led = macropad.device.led.take();
+        assert!(Mapping::validate_config(&macropad, Some(&profile(0x8840))).is_err());
+        macropad.layers[0].led = Some(Led {
+            mode: LedMode::Name("shock2".to_string()),
+            color: None,
+        });
+        Mapping::validate_config(&macropad, Some(&profile(0x8840)))?;
+        Ok(())
+    }
+
+    #[test]
+    fn mapping_pretty() {
+        assert_eq!(Mapping::pretty("ctrl-shift-v"), "Ctrl+Shift+V");
+        assert_eq!(Mapping::pretty("ctrl-a,ctrl-s"), "Ctrl+A, Ctrl+S");
+        assert_eq!(Mapping::pretty("rctrl-pageup"), "RCtrl+PageUp");
+        assert_eq!(Mapping::pretty("shift-2"), "Shift+2");
+        assert_eq!(Mapping::pretty("volumeup"), "VolumeUp");
+        assert_eq!(Mapping::pretty("ctrl-wheeldown"), "Ctrl+WheelDown");
+        assert_eq!(Mapping::pretty(""), "");
+    }
+
     #[test]
     fn mapping_validate() -> anyhow::Result<()> {
         Mapping::validate("./mapping.ron", None)?;
@@ -425,7 +901,7 @@ mod tests {
 
     #[test]
     fn mapping_mismatch() {
-        assert!(Mapping::validate("./mapping.ron", Some(0x8890)).is_err());
+        assert!(Mapping::validate("./mapping.ron", Some(&profile(0x8890))).is_err());
     }
 
     #[test]
@@ -433,10 +909,10 @@ mod tests {
         assert!(Mapping::validate_key_mapping(
             &Button {
                 delay: 6001,
-                mapping: "t,e,s,t".to_string()
+                mapping: "t,e,s,t".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_884X,
-            Some(0x8840)
+            Some(&profile(0x8840))
         )
         .is_err());
     }
@@ -447,17 +923,17 @@ mod tests {
             &Button {
                 delay: 6000,
                 mapping: "t,e,s,t".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_884X,
-            Some(0x8840),
+            Some(&profile(0x8840)),
         )?;
         Mapping::validate_key_mapping(
             &Button {
                 delay: 1234,
                 mapping: "t,e,s,t".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_8890,
-            Some(0x8890),
+            Some(&profile(0x8890)),
         )?;
         Ok(())
     }
@@ -467,28 +943,28 @@ mod tests {
         assert!(Mapping::validate_key_mapping(
             &Button {
                 delay: 0,
-                mapping: "ctrl-a,shift-s".to_string()
+                mapping: "ctrl-a,shift-s".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_8890,
-            Some(0x8890)
+            Some(&profile(0x8890))
         )
         .is_err());
         assert!(Mapping::validate_key_mapping(
             &Button {
                 delay: 0,
-                mapping: "alt-a,ctrl-s".to_string()
+                mapping: "alt-a,ctrl-s".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_8890,
-            Some(0x8890)
+            Some(&profile(0x8890))
         )
         .is_err());
         assert!(Mapping::validate_key_mapping(
             &Button {
                 delay: 0,
-                mapping: "shift-a,alt-s".to_string()
+                mapping: "shift-a,alt-s".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_8890,
-            Some(0x8890)
+            Some(&profile(0x8890))
         )
         .is_err());
     }
@@ -499,17 +975,127 @@ mod tests {
             &Button {
                 delay: 0,
                 mapping: "1,2,3,4,5".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_8890,
-            Some(0x8890),
+            Some(&profile(0x8890)),
         )?;
         assert!(Mapping::validate_key_mapping(
             &Button {
                 delay: 0,
-                mapping: "1,2,3,4,5,6".to_string()
+                mapping: "1,2,3,4,5,6".to_string(),
+                ..Button::new()
+            },
+            Some(&profile(0x8890))
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mapping_key_classes() -> anyhow::Result<()> {
+        let btn = Button {
+            mapping: "calculator".to_string(),
+            ..Button::new()
+        };
+        Mapping::validate_key_mapping(&btn, Some(&profile(0x8840)))?;
+        assert!(Mapping::validate_key_mapping(&btn, Some(&profile(0x8890))).is_err());
+
+        let mut custom = profile(0x8840);
+        custom.key_classes.retain(|c| *c != KeyClass::Mouse);
+        let btn = Button {
+            mapping: "ctrl-click".to_string(),
+            ..Button::new()
+        };
+        Mapping::validate_key_mapping(&btn, Some(&profile(0x8840)))?;
+        assert!(Mapping::validate_key_mapping(&btn, Some(&custom)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mapping_mouse_events() -> anyhow::Result<()> {
+        let mouse = |mapping: &str| Button {
+            mapping: mapping.to_string(),
+            ..Button::new()
+        };
+        for mapping in ["click+rclick", "Shift-WheelDown", "a,click-mclick"] {
+            Mapping::validate_key_mapping(&mouse(mapping), Some(&profile(0x8890)))?;
+        }
+        for mapping in ["win-click", "click-wheelup", "click+a", "ctrl-shift-click"] {
+            assert!(
+                Mapping::validate_key_mapping(&mouse(mapping), None).is_err(),
+                "{mapping}"
+            );
+        }
+        // modifiers are only allowed on the first key press of 0x8890
+        let btn = mouse("a,ctrl-click");
+        Mapping::validate_key_mapping(&mouse("ctrl-click,a"), Some(&profile(0x8890)))?;
+        assert!(Mapping::validate_key_mapping(&btn, Some(&profile(0x8890))).is_err());
+
+        let mut custom = profile(0x8840);
+        custom.key_classes.retain(|c| *c != KeyClass::Modifier);
+        Mapping::validate_key_mapping(&mouse("click"), Some(&custom))?;
+        assert!(Mapping::validate_key_mapping(&mouse("alt-click"), Some(&custom)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mapping_canonical() {
+        for (mapping, canonical) in [
+            ("ctrl-a,ctrl-s", "ctrl-a,ctrl-s"),
+            ("Volumeup", "volumeup"),
+            ("alt-Ctrl-F3", "ctrl-alt-f3"),
+            ("shift-opt-cmd-x", "shift-alt-win-x"),
+            ("prev,next", "previous,next"),
+            ("Ctrl-Click-rclick", "ctrl-click+rclick"),
+            ("a,<110>", "a,<110>"),
+        ] {
+            assert_eq!(Mapping::canonical(mapping), canonical);
+        }
+    }
+
+    #[test]
+    fn mapping_mixed_key_classes() -> anyhow::Result<()> {
+        let check = |mapping: &str, pid| {
+            Mapping::check_key_classes(mapping, Some(&profile(pid))).map_err(|e| e.to_string())
+        };
+        // one key type per sequence on 0x884x
+        for mapping in [
+            "a,b",
+            "ctrl-a,shift-s",
+            "volumeup",
+            "ctrl-click",
+            "click+rclick",
+        ] {
+            assert_eq!(check(mapping, 0x8840), Ok(()), "{mapping}");
+        }
+        assert_eq!(
+            check("a,volumeup,b", 0x8840),
+            Err("884x (0x8840) macropad programs a button with keyboard keys or a single media key - 'a,b' would be lost from 'a,volumeup,b'".to_string())
+        );
+        assert!(check("click,b", 0x8842)
+            .unwrap_err()
+            .contains("single mouse event - 'b'"));
+        assert!(check("play,next", 0x8842).is_err());
+
+        // a message per key press on 0x8890
+        for mapping in ["a,volumeup", "click,b", "play,next"] {
+            assert_eq!(check(mapping, 0x8890), Ok(()), "{mapping}");
+        }
+
+        // media keys never carry modifiers
+        assert_eq!(
+            Mapping::check_key_classes("ctrl-volumeup", None).map_err(|e| e.to_string()),
+            Err(
+                "media keys are sent on their own - 'ctrl' would be lost from 'ctrl-volumeup'"
+                    .to_string()
+            )
+        );
+        assert!(Mapping::validate_key_mapping(
+            &Button {
+                mapping: "a,volumeup".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_8890,
-            Some(0x8890)
+            Some(&profile(0x8842))
         )
         .is_err());
         Ok(())
@@ -521,9 +1107,9 @@ mod tests {
             &Button {
                 delay: 0,
                 mapping: "ctrl-a,shift-s".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_884X,
-            Some(0x8840),
+            Some(&profile(0x8840)),
         )?;
         Ok(())
     }
@@ -534,17 +1120,17 @@ mod tests {
             &Button {
                 delay: 0,
                 mapping: "1,2,3,4,5,6,7,8,9,0,a,b,c,d,e,f,g".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_884X,
-            Some(0x8840),
+            Some(&profile(0x8840)),
         )?;
         assert!(Mapping::validate_key_mapping(
             &Button {
                 delay: 0,
-                mapping: "1,2,3,4,5,6,7,8,9,0,a,b,c,d,e,f,g,h".to_string()
+                mapping: "1,2,3,4,5,6,7,8,9,0,a,b,c,d,e,f,g,h".to_string(),
+                ..Button::new()
             },
-            consts::MAX_KEY_PRESSES_884X,
-            Some(0x8840)
+            Some(&profile(0x8840))
         )
         .is_err());
         Ok(())
@@ -0,0 +1,422 @@
+use crate::decoder::Decoder;
+use crate::keyboard::transport::{self, DryRun, Transport, UsbSettings, UsbTransport};
+use crate::keyboard::{Configuration, MediaCode, MouseButton, WellKnownCode};
+use crate::mapping::{Button, Macropad, Mapping};
+
+use anyhow::{ensure, Context as _, Result};
+use itertools::Itertools as _;
+use log::debug;
+use num::FromPrimitive;
+use rusb::{Context, Device, TransferType};
+use std::time::Duration;
+use strum::IntoEnumIterator as _;
+
+/// Report id of the vendor messages (programming and answers)
+const VENDOR_REPORT_ID: u8 = 0x03;
+
+/// Timeout of a read, short so all interfaces are polled in turn
+const POLL_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Format of the input reports of an interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// boot protocol keyboard: modifier, reserved, 6 key codes
+    Keyboard,
+    /// boot protocol mouse: buttons, x, y, wheel
+    Mouse,
+    /// reports starting with a report id
+    ReportId,
+}
+
+impl ReportFormat {
+    /// Returns the report format of a HID interface
+    ///
+    /// #Arguments
+    /// `subclass` - interface subclass (1 for boot interfaces)
+    /// `protocol` - interface protocol (1 keyboard, 2 mouse)
+    ///
+    pub fn of(subclass: u8, protocol: u8) -> Self {
+        match (subclass, protocol) {
+            (1, 1) => Self::Keyboard,
+            (1, 2) => Self::Mouse,
+            _ => Self::ReportId,
+        }
+    }
+}
+
+/// Binding of the configuration that produces an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// layer (one based)
+    pub layer: usize,
+    /// position of the button or knob action, eg. row 1 col 2
+    pub position: String,
+    /// key number used for programming, when known
+    pub key_num: Option<u8>,
+    /// true if the event is the whole mapping and not one key press of it
+    pub exact: bool,
+}
+
+/// Prints the key presses of the macropad and which binding of the
+/// configuration most likely produced them
+pub struct Monitor {
+    /// configuration programmed into the device, if known
+    macropad: Option<Macropad>,
+}
+
+impl Monitor {
+    /// Returns the monitor
+    ///
+    /// #Arguments
+    /// `macropad` - Optional configuration used to locate the bindings of the events
+    ///
+    pub fn new(macropad: Option<Macropad>) -> Self {
+        Self { macropad }
+    }
+
+    /// Returns the event of an input report in the mapping syntax, None for
+    /// releases and reports that are not key presses
+    ///
+    /// #Arguments
+    /// `format` - format of the reports of the interface
+    /// `report` - input report
+    ///
+    pub fn decode(format: ReportFormat, report: &[u8]) -> Option<String> {
+        match format {
+            ReportFormat::Keyboard => Self::decode_keyboard(report),
+            ReportFormat::Mouse => Self::decode_mouse(report),
+            ReportFormat::ReportId => {
+                let (id, data) = report.split_first()?;
+                if *id == VENDOR_REPORT_ID {
+                    return None;
+                }
+                // the report id differs between firmwares, so go by the length
+                match data.len() {
+                    8 => Self::decode_keyboard(data),
+                    2 => Self::decode_media(data),
+                    3..=5 => Self::decode_mouse(data),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn decode_keyboard(report: &[u8]) -> Option<String> {
+        if report.len() < 3 {
+            return None;
+        }
+        let mut keys = Vec::new();
+        let modifier = Decoder::modifier_to_str(report[0]);
+        if !modifier.is_empty() {
+            keys.push(modifier);
+        }
+        for code in report[2..].iter().filter(|c| **c != 0) {
+            match <WellKnownCode as FromPrimitive>::from_u8(*code) {
+                Some(wkc) => keys.push(wkc.to_string()),
+                None => keys.push(format!("<{code}>")),
+            }
+        }
+        (!keys.is_empty()).then(|| keys.join("-"))
+    }
+
+    fn decode_media(report: &[u8]) -> Option<String> {
+        let code = u16::from_le_bytes([report[0], report[1]]);
+        if code == 0 {
+            return None;
+        }
+        match <MediaCode as FromPrimitive>::from_u16(code) {
+            Some(mc) => Some(mc.to_string()),
+            None => Some(format!("<media 0x{code:04x}>")),
+        }
+    }
+
+    fn decode_mouse(report: &[u8]) -> Option<String> {
+        let mut mouse: Vec<String> = MouseButton::iter()
+            .filter(|b| report[0] >> (*b as u8) & 1 == 1)
+            .map(|b| b.to_string())
+            .collect();
+        match report.get(3).map(|w| *w as i8) {
+            Some(w) if w > 0 => mouse.push("wheelup".to_string()),
+            Some(w) if w < 0 => mouse.push("wheeldown".to_string()),
+            _ => (),
+        }
+        // buttons held together are written like in mappings, eg. click+rclick
+        (!mouse.is_empty()).then(|| mouse.join("+"))
+    }
+
+    /// Returns the bindings of the configuration that produce the event, the
+    /// bindings mapped to exactly the event first
+    ///
+    /// #Arguments
+    /// `event` - decoded event
+    /// `config` - Optional driver numbering the keys
+    ///
+    pub fn locate(&self, event: &str, config: Option<&dyn Configuration>) -> Vec<Binding> {
+        let Some(macropad) = &self.macropad else {
+            return Vec::new();
+        };
+        let device = &macropad.device;
+        let layout = config.and_then(|c| c.device_layout(device).ok());
+
+        // aliases and the order of the modifiers do not matter
+        let event = Mapping::canonical(event);
+        let mut bindings = Vec::new();
+        let mut check = |layer: usize, position: String, key_num: Option<u8>, btn: &Button| {
+            let mapping = Mapping::canonical(&btn.mapping);
+            let exact = mapping == event;
+            if exact || mapping.split(',').any(|press| press == event) {
+                bindings.push(Binding {
+                    layer: layer + 1,
+                    position,
+                    key_num,
+                    exact,
+                });
+            }
+        };
+        for (layer, l) in macropad.layers.iter().enumerate() {
+            for (row, buttons) in l.buttons.iter().enumerate() {
+                for (col, btn) in buttons.iter().enumerate() {
+                    let key_num = layout
+                        .as_ref()
+                        .and_then(|l| l.get(row).and_then(|r| r.get(col)))
+                        .copied();
+                    check(
+                        layer,
+                        format!("row {} col {}", row + 1, col + 1),
+                        key_num,
+                        btn,
+                    );
+                }
+            }
+            for (k, knob) in l.knobs.iter().enumerate() {
+                for (action, (name, btn)) in
+                    [("ccw", &knob.ccw), ("press", &knob.press), ("cw", &knob.cw)]
+                        .into_iter()
+                        .enumerate()
+                {
+                    let key_num = config.map(|c| c.knob_base() + (k * 3 + action) as u8);
+                    check(layer, format!("knob {} {name}", k + 1), key_num, btn);
+                }
+            }
+        }
+        bindings.sort_by_key(|b| !b.exact);
+        bindings
+    }
+
+    /// Returns the line printed for an event
+    ///
+    /// #Arguments
+    /// `event` - decoded event
+    /// `report` - input report
+    /// `config` - Optional driver numbering the keys
+    ///
+    pub fn describe(
+        &self,
+        event: &str,
+        report: &[u8],
+        config: Option<&dyn Configuration>,
+    ) -> String {
+        let hex = DryRun::hex(report).join(" ");
+        let bindings = self.locate(event, config);
+        let Some((first, others)) = bindings.split_first() else {
+            return if self.macropad.is_some() {
+                format!("{event:<20} [{hex}] -> not in the configuration")
+            } else {
+                format!("{event:<20} [{hex}]")
+            };
+        };
+        let name = |b: &Binding| match b.key_num {
+            Some(key_num) => format!("layer {} {} (key 0x{key_num:02x})", b.layer, b.position),
+            None => format!("layer {} {}", b.layer, b.position),
+        };
+        let mut line = format!("{event:<20} [{hex}] -> {}", name(first));
+        if !first.exact {
+            line += " (one key press of it)";
+        }
+        if !others.is_empty() {
+            line += &format!(", or {}", others.iter().map(name).join(", "));
+        }
+        line
+    }
+
+    /// Prints the events of the device until the user presses Ctrl-C
+    ///
+    /// #Arguments
+    /// `device` - usb device
+    /// `settings` - timing of the transfers
+    /// `config` - Optional driver numbering the keys
+    ///
+    pub fn run(
+        &self,
+        device: &Device<Context>,
+        settings: UsbSettings,
+        config: Option<&dyn Configuration>,
+    ) -> Result<()> {
+        let mut reader = HidReader::open(device, settings)?;
+        println!("press the keys and turn the knobs, Ctrl-C to stop");
+        while let Some((event, report)) = reader.next_event()? {
+            println!("{}", self.describe(&event, &report, config));
+        }
+        Ok(())
+    }
+}
+
+/// Reads the input reports of the HID interfaces of a device. The interfaces
+/// are claimed, so key presses do not reach the system meanwhile
+pub struct HidReader {
+    /// interface number, report format and transport of each HID interface
+    interfaces: Vec<(u8, ReportFormat, UsbTransport)>,
+}
+
+impl HidReader {
+    /// Claims the HID interfaces with an interrupt IN endpoint of the device
+    ///
+    /// #Arguments
+    /// `device` - usb device
+    /// `settings` - timing of the transfers
+    ///
+    pub fn open(device: &Device<Context>, settings: UsbSettings) -> Result<Self> {
+        let conf_desc = device
+            .config_descriptor(0)
+            .context("get config #0 descriptor")?;
+        let settings = UsbSettings {
+            timeout: POLL_TIMEOUT,
+            retries: 0,
+            ..settings
+        };
+
+        // one claimed handle per interface
+        let mut interfaces = Vec::new();
+        for intf in conf_desc.interfaces() {
+            for desc in intf.descriptors().filter(|d| d.class_code() == 3) {
+                let Some(endpoint) = desc.endpoint_descriptors().find(|e| {
+                    e.transfer_type() == TransferType::Interrupt
+                        && e.direction() == rusb::Direction::In
+                }) else {
+                    continue;
+                };
+                let format = ReportFormat::of(desc.sub_class_code(), desc.protocol_code());
+                debug!(
+                    "interface {} endpoint 0x{:02x} {format:?}",
+                    desc.interface_number(),
+                    endpoint.address()
+                );
+                let handle = device.open().context("open USB device")?;
+                let transport = UsbTransport::open(
+                    handle,
+                    desc.interface_number(),
+                    0xff,
+                    endpoint.address(),
+                    settings,
+                )?;
+                interfaces.push((desc.interface_number(), format, transport));
+            }
+        }
+        ensure!(!interfaces.is_empty(), "device has no HID input interfaces");
+        Ok(Self { interfaces })
+    }
+
+    /// Waits for the next key press and returns it in the mapping syntax with the
+    /// report it was decoded from, None when the user pressed Ctrl-C
+    ///
+    pub fn next_event(&mut self) -> Result<Option<(String, Vec<u8>)>> {
+        let mut buf = [0u8; 64];
+        while !transport::interrupted() {
+            for (interface, format, transport) in &mut self.interfaces {
+                let read = transport.read(&mut buf)?;
+                if read == 0 {
+                    continue;
+                }
+                let report = &buf[..read];
+                match Monitor::decode(*format, report) {
+                    Some(event) => return Ok(Some((event, report.to_vec()))),
+                    None => debug!("interface {interface}: {report:02x?}"),
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts;
+    use crate::keyboard::new_keyboard;
+    use crate::mapping::Macropad;
+    use crate::monitor::{Monitor, ReportFormat};
+    use crate::profile::Profiles;
+
+    #[test]
+    fn decode_reports() {
+        let keyboard = ReportFormat::of(1, 1);
+        assert_eq!(
+            Monitor::decode(keyboard, &[0x01, 0, 0x04, 0, 0, 0, 0, 0]).as_deref(),
+            Some("ctrl-a")
+        );
+        assert_eq!(Monitor::decode(keyboard, &[0; 8]), None, "release");
+        let report_id = ReportFormat::of(0, 0);
+        assert_eq!(
+            Monitor::decode(report_id, &[0x02, 0xe9, 0x00]).as_deref(),
+            Some("volumeup")
+        );
+        assert_eq!(
+            Monitor::decode(report_id, &[0x01, 0x02, 0, 0x05, 0, 0, 0, 0, 0]).as_deref(),
+            Some("shift-b")
+        );
+        assert_eq!(
+            Monitor::decode(report_id, &[0x04, 0x01, 0, 0, 0]).as_deref(),
+            Some("click")
+        );
+        assert_eq!(
+            Monitor::decode(ReportFormat::of(1, 2), &[0, 0, 0, 0xff]).as_deref(),
+            Some("wheeldown")
+        );
+        assert_eq!(
+            Monitor::decode(report_id, &[0x04, 0x03, 0, 0, 0]).as_deref(),
+            Some("click+rclick")
+        );
+        assert_eq!(Monitor::decode(report_id, &[0x03, 0xfb, 0x06, 0x01]), None);
+    }
+
+    #[test]
+    fn locate_bindings() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(2, 3, 1);
+        mp.layers[0].buttons[1][2].mapping = "ctrl-a".to_string();
+        mp.layers[1].buttons[0][0].mapping = "b,ctrl-a".to_string();
+        mp.layers[0].knobs[0].cw.mapping = "volumeup".to_string();
+        let monitor = Monitor::new(Some(mp));
+        let profile = Profiles::builtin().get(consts::VENDOR_ID, 0x8840)?.clone();
+        let driver = new_keyboard(&profile, None)?;
+
+        let bindings = monitor.locate("ctrl-a", Some(driver.as_ref()));
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(
+            (bindings[0].layer, bindings[0].position.as_str()),
+            (1, "row 2 col 3")
+        );
+        assert_eq!(bindings[0].key_num, Some(6));
+        assert!(!bindings[1].exact);
+
+        let line = monitor.describe("volumeup", &[0x02, 0xe9, 0x00], Some(driver.as_ref()));
+        assert!(line.ends_with("-> layer 1 knob 1 cw (key 0x12)"), "{line}");
+        assert!(monitor.locate("x", None).is_empty());
+
+        // mappings are compared in canonical form
+        let mut mp = Macropad::new(1, 3, 0);
+        mp.layers[0].buttons[0][0].mapping = "Alt-Ctrl-A".to_string();
+        mp.layers[0].buttons[0][1].mapping = "rclick+click".to_string();
+        mp.layers[0].buttons[0][2].mapping = "opt-b".to_string();
+        let monitor = Monitor::new(Some(mp));
+        for (event, position) in [
+            ("ctrl-alt-a", "row 1 col 1"),
+            ("click+rclick", "row 1 col 2"),
+            ("alt-b", "row 1 col 3"),
+        ] {
+            let bindings = monitor.locate(event, None);
+            assert_eq!(bindings.len(), 1, "{event}");
+            assert_eq!(bindings[0].position, position);
+            assert!(bindings[0].exact);
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,46 @@
-use crate::consts::VENDOR_ID;
-use crate::keyboard::LedColor;
+use crate::consts::{self, VENDOR_ID};
+use crate::convert::Strategy;
+use crate::keyboard::transport::UsbSettings;
+use crate::keyboard::{LedColor, LedMode};
 use crate::parse;
+use crate::profile::DriverFamily;
+use crate::sheet::SheetFormat;
+use crate::udev;
+use crate::view::ViewFormat;
 use clap::{Args, Parser, Subcommand};
 use std::num::ParseIntError;
+use std::time::Duration;
 
 #[derive(Parser)]
 pub struct Options {
     #[command(subcommand)]
     pub command: Command,
 
+    /// Additional device profile file (ron or toml), can be repeated
+    #[arg(long = "profile", global = true)]
+    pub profile_files: Vec<String>,
+
+    /// Record the USB packets exchanged with the device to a session file (json)
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<String>,
+
+    /// Play back a recorded session file instead of talking to a device
+    #[arg(long, global = true, value_name = "FILE")]
+    pub replay: Option<String>,
+
+    /// Timeout of a USB transfer in milliseconds
+    #[arg(long, global = true, value_name = "MS", default_value_t = consts::DEFAULT_TIMEOUT.as_millis() as u64,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+
+    /// Minimum time between two messages sent to the device in milliseconds
+    #[arg(long, global = true, value_name = "MS", default_value_t = 0)]
+    pub pace: u64,
+
+    /// Number of times a failed USB transfer is retried
+    #[arg(long, global = true, default_value_t = consts::DEFAULT_RETRIES)]
+    pub retries: u8,
+
     #[clap(flatten)]
     pub devel_options: DevelOptions,
 }
@@ -25,6 +57,10 @@ pub struct DevelOptions {
     #[arg(long, value_parser=u16_hex_or_decimal, hide=true)]
     pub product_id: Option<u16>,
 
+    /// Driver family used for compatible devices without a device profile (untested)
+    #[arg(long, value_enum, hide = true)]
+    pub driver: Option<DriverFamily>,
+
     #[arg(long, value_parser=parse_address, hide=true)]
     pub address: Option<(u8, u8)>,
 
@@ -68,10 +104,26 @@ pub fn u8_hex_or_decimal(s: &str) -> Result<u8, ParseIntError> {
     }
 }
 
+impl Options {
+    /// Returns the timing of the USB transfers set on the command line
+    ///
+    pub fn usb_settings(&self) -> UsbSettings {
+        UsbSettings {
+            timeout: Duration::from_millis(self.timeout),
+            pace: Duration::from_millis(self.pace),
+            retries: self.retries,
+        }
+    }
+}
+
 fn parse_address(s: &str) -> std::result::Result<(u8, u8), nom::error::Error<String>> {
     parse::from_str(parse::address, s)
 }
 
+fn parse_geometry(s: &str) -> std::result::Result<(u8, u8, u8), nom::error::Error<String>> {
+    parse::from_str(parse::geometry, s)
+}
+
 #[derive(Subcommand)]
 pub enum Command {
     /// Show supported keys and modifiers
@@ -90,6 +142,66 @@ pub enum Command {
         /// Validate against connected device
         #[clap(short, default_value_t = false)]
         device_connected: bool,
+
+        /// List every binding with its label and description once validated
+        #[clap(short, long, default_value_t = false)]
+        verbose: bool,
+
+        /// Display the configuration once validated
+        #[arg(long, value_enum)]
+        view: Option<ViewFormat>,
+
+        /// Overlay the key numbers used for programming (grid view only)
+        #[clap(short, long, default_value_t = false)]
+        key_numbers: bool,
+    },
+
+    /// Show key mappings config as a grid of buttons and knobs
+    Show {
+        /// Configuration file in ron format
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Product ID used to overlay key numbers (each product differs)
+        #[clap(short, value_parser=u16_hex_or_decimal)]
+        product_id: Option<u16>,
+
+        /// Overlay the key numbers used for programming (requires product id)
+        #[clap(short, long, default_value_t = false)]
+        key_numbers: bool,
+    },
+
+    /// Edit key mappings config interactively in the terminal
+    Edit {
+        /// Configuration file in ron format
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Product ID to validate mappings against (each product differs)
+        #[clap(short, value_parser=u16_hex_or_decimal)]
+        product_id: Option<u16>,
+    },
+
+    /// Describe every binding in the key mappings config
+    Describe {
+        /// Configuration file in ron format
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+    },
+
+    /// Export a printable cheat sheet of the key mappings
+    ExportSheet {
+        /// Configuration file in ron format
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Output format (svg writes one file per layer)
+        #[arg(short, long, value_enum, default_value_t = SheetFormat::Html)]
+        format: SheetFormat,
+
+        /// Output file name without extension
+        #[clap(short, long, default_value = "./mapping")]
+        output: String,
     },
 
     /// Program key mappings
@@ -97,6 +209,14 @@ pub enum Command {
         /// Configuration file in ron format
         #[clap(short, long, default_value = "./mapping.ron")]
         config_file: String,
+
+        /// Print the messages that would be sent instead of programming the device
+        #[clap(long, default_value_t = false, requires = "product_id")]
+        dry_run: bool,
+
+        /// Product id of the device to build the messages for (with --dry-run)
+        #[clap(short, long, value_parser=u16_hex_or_decimal, requires = "dry_run")]
+        product_id: Option<u16>,
     },
 
     /// Read configuration from device
@@ -104,28 +224,139 @@ pub enum Command {
         /// Layer to read data for (layer is one based; 0 reads all layers)
         #[clap(short, long, default_value_t = 0)]
         layer: u8,
+
+        /// How to display the configuration
+        #[arg(long, value_enum, default_value_t = ViewFormat::Ron)]
+        view: ViewFormat,
+
+        /// Overlay the key numbers used for programming (grid view only)
+        #[clap(short, long, default_value_t = false)]
+        key_numbers: bool,
     },
 
     /// Select LED backlight mode
     Led(LedCommand),
+
+    /// Recover the configuration programmed by the vendor software from a USB
+    /// capture (usbmon or USBPcap, pcap or pcapng)
+    ImportCapture {
+        /// Capture file
+        file: String,
+
+        /// Product ID of the device when the capture lacks its device descriptor
+        #[clap(short, value_parser=u16_hex_or_decimal)]
+        product_id: Option<u16>,
+
+        /// Configuration file to write (ron); printed when not given
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+
+    /// Print the key presses of the device and the bindings that produce them
+    Monitor {
+        /// Configuration file in ron format programmed into the device
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+    },
+
+    /// Convert a configuration to a device with another number of buttons and knobs
+    Convert {
+        /// Configuration file in ron format
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Rows, columns and knobs of the target device, eg. 2x3x1
+        #[clap(long, value_parser=parse_geometry)]
+        to: (u8, u8, u8),
+
+        /// How the buttons that do not fit are handled
+        #[clap(short, long, value_enum, default_value_t = Strategy::Reflow)]
+        strategy: Strategy,
+
+        /// Product ID of the target device to validate the converted configuration with
+        #[clap(short, value_parser=u16_hex_or_decimal)]
+        product_id: Option<u16>,
+
+        /// Configuration file to write (ron); printed when not given
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+
+    /// Format configuration files: canonical mappings, aligned buttons, comments kept
+    Fmt {
+        /// Configuration files in ron format
+        #[clap(default_value = "./mapping.ron")]
+        files: Vec<String>,
+
+        /// Only check that the files are formatted, fail when they are not
+        #[clap(long, default_value_t = false)]
+        check: bool,
+    },
+
+    /// Find the physical layout of the device by pressing its keys in visual order
+    Calibrate {
+        /// Configuration file in ron format whose device section is updated and
+        /// programmed into the device once calibrated
+        #[clap(short, long)]
+        config_file: Option<String>,
+
+        /// Rows of the pad as seen by the user (default: from the configuration
+        /// file or the device)
+        #[clap(long)]
+        rows: Option<u8>,
+
+        /// Columns of the pad as seen by the user
+        #[clap(long)]
+        cols: Option<u8>,
+
+        /// Number of knobs
+        #[clap(long)]
+        knobs: Option<u8>,
+    },
+
+    /// Check access to the connected devices and print the problems found with fixes
+    Doctor,
+
+    /// Generate udev rules giving access to the supported devices (linux)
+    UdevRules {
+        /// Mode of the device nodes
+        #[clap(long, default_value = "666")]
+        mode: String,
+
+        /// Group of the device nodes
+        #[clap(long, default_value = "users")]
+        group: String,
+
+        /// Give access to the user logged in at the seat instead of setting mode and group
+        #[clap(long, conflicts_with_all = ["mode", "group"])]
+        uaccess: bool,
+
+        /// Write the rules to the rules directory instead of printing them
+        #[clap(long, value_name = "DIR", num_args = 0..=1, default_missing_value = udev::RULES_DIR)]
+        install: Option<String>,
+    },
+
+    /// Program devices with the key mappings whenever they are plugged in
+    Watch {
+        /// Configuration file in ron format, read again for every device
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Only program devices plugged into this port (bus-port.port..., eg. 1-2.3)
+        #[clap(long)]
+        port: Option<String>,
+
+        /// Seconds during which a device enumerating again on the same port is not programmed again
+        #[clap(long, default_value_t = 5)]
+        debounce: u64,
+    },
 }
 
 #[derive(Parser, Clone, Default, Debug)]
 pub struct LedCommand {
-    /// Index of LED modes
-    /// --------0x8840----------
-    /// 0 - LEDs off
-    /// 1 - backlight always on with LedColor
-    /// 2 - no backlight, shock with LedColor when key pressed
-    /// 3 - no backlight, shock2 when LedColor when key pressed
-    /// 4 - no backlight, light up key with LedColor when pressed
-    /// 5 - backlight white always on
-    /// --------0x8890---color is not supported-------
-    /// 0 - LEDs off
-    /// 1 - LED on for last pushed key
-    /// 2 - cycle through colors & buttons
-    #[clap(verbatim_doc_comment)]
-    pub index: u8,
+    /// LED mode, by number or name (use --list to show the modes of the device)
+    #[arg(required_unless_present = "list")]
+    pub mode: Option<LedMode>,
 
     // Layer to set the LED
     #[clap(default_value_t = 1)]
@@ -138,4 +369,8 @@ pub struct LedCommand {
     /// Color to apply with mode
     #[arg(value_enum, verbatim_doc_comment)]
     pub led_color: Option<LedColor>,
+
+    /// List the LED modes supported by the connected device
+    #[arg(long, conflicts_with_all = ["mode", "layer", "led_color"])]
+    pub list: bool,
 }
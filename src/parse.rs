@@ -20,6 +20,14 @@ pub fn address(input: &str) -> IResult<&str, (u8, u8)> {
     separated_pair(byte, char(':'), map_res(digit1, u8::from_str)).parse(input)
 }
 
+/// Parses a string like "2x3x1" (rows x columns x knobs) into (u8, u8, u8)
+pub fn geometry(input: &str) -> IResult<&str, (u8, u8, u8)> {
+    let byte = || map_res(digit1, u8::from_str);
+    (byte(), char('x'), byte(), char('x'), byte())
+        .map(|(rows, _, cols, _, knobs)| (rows, cols, knobs))
+        .parse(input)
+}
+
 /// Runs a parser and ensures the entire input is consumed
 pub fn parse<'a, O, E, P>(parser: P, input: &'a str) -> Result<O, E>
 where
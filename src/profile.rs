@@ -0,0 +1,471 @@
+use crate::keyboard::{LedMode, MediaCode};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum as _;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Built-in device profiles
+const BUILTIN_PROFILES: &str = include_str!("profiles.ron");
+
+/// Media keys supported by devices with the `BasicMedia` key class
+const BASIC_MEDIA: [MediaCode; 6] = [
+    MediaCode::Play,
+    MediaCode::Previous,
+    MediaCode::Next,
+    MediaCode::Mute,
+    MediaCode::VolumeUp,
+    MediaCode::VolumeDown,
+];
+
+/// Family of driver that speaks the protocol of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum DriverFamily {
+    /// 0x8840 / 0x8842 style protocol
+    #[value(name = "884x")]
+    K884x,
+    /// 0x8890 style protocol
+    #[value(name = "8890")]
+    K8890,
+}
+
+impl DriverFamily {
+    /// Returns true if a sequence of key presses can mix keyboard keys, media keys
+    /// and mouse events. 0x884x messages have one key type for the whole sequence,
+    /// 0x8890 sends a message per key press
+    ///
+    pub fn mixes_key_classes(&self) -> bool {
+        match self {
+            DriverFamily::K884x => false,
+            DriverFamily::K8890 => true,
+        }
+    }
+}
+
+/// Variant of the 0x884x messages reading the device type and the configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Probe {
+    /// messages of the 0x8840 products
+    K8840,
+    /// messages of the 0x8842 products
+    #[default]
+    K8842,
+}
+
+/// Classes of keys a device can be programmed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyClass {
+    /// Regular keyboard keys
+    Keyboard,
+    /// Modifier keys (ctrl, shift, ...)
+    Modifier,
+    /// All media keys
+    Media,
+    /// Only play, previous, next, mute, volumeup and volumedown
+    BasicMedia,
+    /// Mouse buttons and wheel
+    Mouse,
+}
+
+/// Button layout of a variant of a device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    /// Number of rows
+    pub rows: u8,
+    /// Number of columns
+    pub cols: u8,
+}
+
+/// Everything known about a device identified by its vendor/product id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    /// Name shown to the user
+    pub name: String,
+    /// USB vendor id
+    pub vendor_id: u16,
+    /// USB product id
+    pub product_id: u16,
+    /// Driver used to talk to the device
+    pub driver: DriverFamily,
+    /// Number of layers
+    pub layers: u8,
+    /// Maximum number of key presses that can be assigned to a key
+    pub max_key_presses: usize,
+    /// Whether a delay between key presses is supported
+    pub delay: bool,
+    /// Whether the configuration can be read back from the device
+    pub read_config: bool,
+    /// Whether modifiers are only allowed on the first key of a sequence
+    pub modifiers_first_only: bool,
+    /// Classes of keys the device can be programmed with
+    pub key_classes: Vec<KeyClass>,
+    /// Key number of the first knob action
+    pub knob_base: u8,
+    /// Known button layouts, used to work out rows/cols from the number of keys
+    pub geometries: Vec<Geometry>,
+    /// Names of the LED modes, in the order of their number (empty when there are no LEDs)
+    #[serde(default)]
+    pub led_modes: Vec<String>,
+    /// Whether a color can be set for the LED mode
+    #[serde(default)]
+    pub led_color: bool,
+    /// Whether the LEDs are set per layer rather than once for the device
+    #[serde(default)]
+    pub led_per_layer: bool,
+    /// Messages reading the device type and configuration of 0x884x devices
+    #[serde(default)]
+    pub probe: Probe,
+}
+
+impl DeviceProfile {
+    /// Returns true if keys of the specified class can be programmed
+    ///
+    /// #Arguments
+    /// `class` - class of key
+    ///
+    pub fn supports(&self, class: KeyClass) -> bool {
+        self.key_classes.contains(&class)
+    }
+
+    /// Returns true if the media key can be programmed
+    ///
+    /// #Arguments
+    /// `code` - media key
+    ///
+    pub fn supports_media(&self, code: MediaCode) -> bool {
+        self.supports(KeyClass::Media)
+            || (self.supports(KeyClass::BasicMedia) && BASIC_MEDIA.contains(&code))
+    }
+
+    /// Returns the rows and columns of the variant with the specified number of keys
+    ///
+    /// #Arguments
+    /// `num_keys` - number of keys reported by the device
+    ///
+    pub fn geometry(&self, num_keys: u8) -> Result<(u8, u8)> {
+        self.geometries
+            .iter()
+            .find(|g| g.rows * g.cols == num_keys)
+            .map(|g| (g.rows, g.cols))
+            .ok_or_else(|| anyhow!("unable to guess rows/cols for {num_keys}"))
+    }
+
+    /// Returns the number of the LED mode or an error if the device does not support it
+    ///
+    /// #Arguments
+    /// `mode` - LED mode by number or name
+    ///
+    pub fn led_mode(&self, mode: &LedMode) -> Result<u8> {
+        if self.led_modes.is_empty() {
+            return Err(anyhow!("{} macropad has no LEDs", self.name));
+        }
+        let index = match mode {
+            LedMode::Index(index) => Some(*index as usize).filter(|i| *i < self.led_modes.len()),
+            LedMode::Name(name) => self
+                .led_modes
+                .iter()
+                .position(|m| m.eq_ignore_ascii_case(name)),
+        };
+        index.map(|i| i as u8).ok_or_else(|| {
+            anyhow!(
+                "LED mode {mode} is not supported by {} macropad - supported modes are {}",
+                self.name,
+                self.led_modes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| format!("{i} ({m})"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
+
+/// File format of device profiles
+#[derive(Debug, Serialize, Deserialize)]
+struct ProfileFile {
+    profile: Vec<DeviceProfile>,
+}
+
+/// Registry of the device profiles
+pub struct Profiles {
+    profiles: Vec<DeviceProfile>,
+    /// devices already warned about being driven by an untested driver
+    warned: Mutex<HashSet<(u16, u16)>>,
+}
+
+impl Profiles {
+    /// Returns the registry with the built-in profiles only
+    ///
+    pub fn builtin() -> Self {
+        let file: ProfileFile =
+            ron::from_str(BUILTIN_PROFILES).expect("built-in profiles are valid");
+        Self {
+            profiles: file.profile,
+            warned: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns the registry with the built-in profiles, the profiles in the user
+    /// profile directory and the specified profile files. Later profiles replace
+    /// earlier ones with the same vendor/product id
+    ///
+    /// #Arguments
+    /// `files` - additional profile files (ron or toml)
+    ///
+    pub fn load(files: &[String]) -> Result<Self> {
+        let mut profiles = Self::builtin();
+        if let Some(dir) = Self::user_dir() {
+            if dir.is_dir() {
+                let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+                    .with_context(|| format!("reading {}", dir.display()))?
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| matches!(Self::extension(p), "ron" | "toml"))
+                    .collect();
+                paths.sort();
+                for path in paths {
+                    profiles.add_file(&path)?;
+                }
+            }
+        }
+        for file in files {
+            profiles.add_file(Path::new(file))?;
+        }
+        Ok(profiles)
+    }
+
+    /// Returns the directory user profiles are loaded from
+    ///
+    pub fn user_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("macropad-tool").join("profiles"))
+    }
+
+    /// Adds the profiles of a ron or toml file to the registry
+    ///
+    /// #Arguments
+    /// `path` - profile file
+    ///
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        debug!("loading profiles from {}", path.display());
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("reading profiles {}", path.display()))?;
+        let file = Self::parse(&data, Self::extension(path) == "toml")
+            .with_context(|| format!("parsing profiles {}", path.display()))?;
+        for profile in file.profile {
+            self.add(profile);
+        }
+        Ok(())
+    }
+
+    /// Adds a profile, replacing the one with the same vendor/product id
+    ///
+    /// #Arguments
+    /// `profile` - profile to add
+    ///
+    pub fn add(&mut self, profile: DeviceProfile) {
+        self.profiles
+            .retain(|p| (p.vendor_id, p.product_id) != (profile.vendor_id, profile.product_id));
+        self.profiles.push(profile);
+    }
+
+    /// Returns the profiles of the registry
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = &DeviceProfile> {
+        self.profiles.iter()
+    }
+
+    /// Returns the profile for the vendor/product id if there is one
+    ///
+    /// #Arguments
+    /// `vid` - vendor id
+    /// `pid` - product id
+    ///
+    pub fn find(&self, vid: u16, pid: u16) -> Option<&DeviceProfile> {
+        self.profiles
+            .iter()
+            .find(|p| p.vendor_id == vid && p.product_id == pid)
+    }
+
+    /// Returns the profile for the vendor/product id or an error if the device
+    /// is unknown
+    ///
+    /// #Arguments
+    /// `vid` - vendor id
+    /// `pid` - product id
+    ///
+    pub fn get(&self, vid: u16, pid: u16) -> Result<&DeviceProfile> {
+        self.find(vid, pid).ok_or_else(|| {
+            anyhow!("Unknown product id 0x{pid:02x} for vendor id 0x{vid:02x}. Add a device profile to support it")
+        })
+    }
+
+    /// Returns the profile for the vendor/product id, optionally forcing the driver
+    /// family. Unknown devices can only be driven when the driver is forced, in which
+    /// case the first profile of that family is used as a template
+    ///
+    /// #Arguments
+    /// `vid` - vendor id
+    /// `pid` - product id
+    /// `driver` - Optional driver family to use regardless of the profile
+    ///
+    pub fn resolve(
+        &self,
+        vid: u16,
+        pid: u16,
+        driver: Option<DriverFamily>,
+    ) -> Result<DeviceProfile> {
+        let Some(driver) = driver else {
+            return self.get(vid, pid).cloned().map_err(|e| {
+                e.context("use --driver to force a driver family for compatible devices")
+            });
+        };
+
+        if let Some(profile) = self.find(vid, pid) {
+            if profile.driver == driver {
+                return Ok(profile.clone());
+            }
+        }
+
+        let template = self.by_driver(driver)?;
+        if self.first_warning(vid, pid) {
+            eprintln!(
+                "Warning - device 0x{vid:04x}:0x{pid:04x} is untested with the {} driver. Use at your own risk",
+                driver.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+            );
+        }
+        Ok(DeviceProfile {
+            name: format!("0x{vid:04x}:0x{pid:04x} ({} compatible)", template.name),
+            vendor_id: vid,
+            product_id: pid,
+            ..template.clone()
+        })
+    }
+
+    /// Returns true the first time it is called for the vendor/product id, so the
+    /// device is warned about once however many times it is resolved
+    ///
+    /// #Arguments
+    /// `vid` - vendor id
+    /// `pid` - product id
+    ///
+    fn first_warning(&self, vid: u16, pid: u16) -> bool {
+        self.warned
+            .lock()
+            .map_or(true, |mut warned| warned.insert((vid, pid)))
+    }
+
+    /// Returns the first profile of the driver family
+    ///
+    /// #Arguments
+    /// `driver` - driver family
+    ///
+    pub fn by_driver(&self, driver: DriverFamily) -> Result<&DeviceProfile> {
+        self.profiles
+            .iter()
+            .find(|p| p.driver == driver)
+            .ok_or_else(|| anyhow!("no device profile for driver family {driver:?}"))
+    }
+
+    fn parse(data: &str, toml: bool) -> Result<ProfileFile> {
+        if toml {
+            Ok(toml::from_str(data)?)
+        } else {
+            Ok(ron::from_str(data)?)
+        }
+    }
+
+    fn extension(path: &Path) -> &str {
+        path.extension().and_then(|e| e.to_str()).unwrap_or("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::VENDOR_ID;
+    use crate::keyboard::{LedMode, MediaCode};
+    use crate::profile::{DriverFamily, KeyClass, Probe, Profiles};
+
+    #[test]
+    fn builtin_profiles() -> anyhow::Result<()> {
+        let profiles = Profiles::builtin();
+        let p = profiles.get(VENDOR_ID, 0x8842)?;
+        assert_eq!(p.driver, DriverFamily::K884x);
+        assert_eq!(p.max_key_presses, 17);
+        assert_eq!(p.knob_base, 0x10);
+        assert_eq!(p.probe, Probe::K8842);
+        assert_eq!(profiles.get(VENDOR_ID, 0x8840)?.probe, Probe::K8840);
+        assert_eq!(p.geometry(12)?, (3, 4));
+        assert!(p.geometry(7).is_err());
+
+        let p = profiles.get(VENDOR_ID, 0x8890)?;
+        assert_eq!(p.driver, DriverFamily::K8890);
+        assert!(!p.delay);
+        assert!(p.supports_media(MediaCode::VolumeUp));
+        assert!(!p.supports_media(MediaCode::Calculator));
+        assert_eq!(p.led_mode(&LedMode::Name("Last-Key".to_string()))?, 1);
+        assert_eq!(p.led_mode(&LedMode::Index(2))?, 2);
+        assert!(p.led_mode(&LedMode::Index(3)).is_err());
+        assert!(p.led_mode(&LedMode::Name("white".to_string())).is_err());
+
+        assert!(profiles.get(VENDOR_ID, 0x1234).is_err());
+        assert!(profiles.get(0x1234, 0x8840).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn forced_driver() -> anyhow::Result<()> {
+        let profiles = Profiles::builtin();
+        assert!(profiles.resolve(0x1234, 0x5678, None).is_err());
+
+        let p = profiles.resolve(0x1234, 0x5678, Some(DriverFamily::K8890))?;
+        assert_eq!((p.vendor_id, p.product_id), (0x1234, 0x5678));
+        assert_eq!(p.driver, DriverFamily::K8890);
+        assert_eq!(p.max_key_presses, 5);
+
+        // known devices keep their profile when the driver matches
+        let p = profiles.resolve(VENDOR_ID, 0x8842, Some(DriverFamily::K884x))?;
+        assert_eq!(&p, profiles.get(VENDOR_ID, 0x8842)?);
+        let p = profiles.resolve(VENDOR_ID, 0x8842, Some(DriverFamily::K8890))?;
+        assert_eq!(p.driver, DriverFamily::K8890);
+
+        // untested devices are warned about once
+        assert!(!profiles.first_warning(VENDOR_ID, 0x8842));
+        assert!(profiles.first_warning(VENDOR_ID, 0x8840));
+        assert!(!profiles.first_warning(VENDOR_ID, 0x8840));
+        Ok(())
+    }
+
+    #[test]
+    fn toml_profiles() -> anyhow::Result<()> {
+        let data = r#"
+            [[profile]]
+            name = "custom"
+            vendor_id = 0x1189
+            product_id = 0x8840
+            driver = "K884x"
+            layers = 2
+            max_key_presses = 10
+            delay = true
+            read_config = false
+            modifiers_first_only = false
+            key_classes = ["Keyboard", "Modifier"]
+            knob_base = 0x10
+            geometries = [{ rows = 1, cols = 4 }]
+        "#;
+        let mut profiles = Profiles::builtin();
+        for p in Profiles::parse(data, true)?.profile {
+            profiles.add(p);
+        }
+        let p = profiles.get(VENDOR_ID, 0x8840)?;
+        assert_eq!(p.name, "custom");
+        assert_eq!(p.layers, 2);
+        assert!(!p.supports(KeyClass::Mouse));
+        assert!(p.led_mode(&LedMode::Index(0)).is_err());
+        assert_eq!(p.geometry(4)?, (1, 4));
+        assert_eq!(profiles.profiles.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use crate::config::Orientation;
+use crate::mapping::{Button, Knob, Macropad, Mapping};
+
+use std::fmt::Write as _;
+
+/// Output format of the cheat sheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SheetFormat {
+    /// One SVG file per layer
+    Svg,
+    /// One HTML page containing every layer
+    Html,
+}
+
+/// Width of a button on the sheet
+const KEY_WIDTH: u32 = 120;
+/// Height of a button on the sheet
+const KEY_HEIGHT: u32 = 80;
+/// Width of a knob on the sheet
+const KNOB_WIDTH: u32 = 170;
+/// Height of a knob on the sheet
+const KNOB_HEIGHT: u32 = 110;
+/// Space between elements on the sheet
+const GAP: u32 = 10;
+/// Space reserved on top of the sheet for the title
+const TITLE_HEIGHT: u32 = 40;
+/// Maximum characters per line of text inside a button
+const LINE_LEN: usize = 16;
+
+pub struct Sheet {}
+
+impl Sheet {
+    /// Returns a SVG diagram of the specified layer
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be rendered
+    /// `layer` - layer to render (zero based)
+    ///
+    pub fn svg(macropad: &Macropad, layer: usize) -> String {
+        let rows = macropad.device.rows as u32;
+        let cols = macropad.device.cols as u32;
+        let knobs = macropad.layers[layer].knobs.len() as u32;
+        let grid_width = cols * (KEY_WIDTH + GAP);
+        let grid_height = rows * (KEY_HEIGHT + GAP);
+
+        // knobs sit on the side of the buttons in horizontal orientations and on
+        // top/bottom of them in vertical ones
+        let (width, height, grid_origin, knob_origin, knob_step) = match macropad.device.orientation
+        {
+            Orientation::Normal => (
+                grid_width + knobs * (KNOB_WIDTH + GAP),
+                grid_height.max(KNOB_HEIGHT + GAP),
+                (0, 0),
+                (grid_width, 0),
+                (KNOB_WIDTH + GAP, 0),
+            ),
+            Orientation::UpsideDown => (
+                grid_width + knobs * (KNOB_WIDTH + GAP),
+                grid_height.max(KNOB_HEIGHT + GAP),
+                (knobs * (KNOB_WIDTH + GAP), 0),
+                (0, 0),
+                (KNOB_WIDTH + GAP, 0),
+            ),
+            Orientation::Clockwise => (
+                grid_width.max(KNOB_WIDTH + GAP),
+                grid_height + knobs * (KNOB_HEIGHT + GAP),
+                (0, 0),
+                (0, grid_height),
+                (0, KNOB_HEIGHT + GAP),
+            ),
+            Orientation::CounterClockwise => (
+                grid_width.max(KNOB_WIDTH + GAP),
+                grid_height + knobs * (KNOB_HEIGHT + GAP),
+                (0, knobs * (KNOB_HEIGHT + GAP)),
+                (0, 0),
+                (0, KNOB_HEIGHT + GAP),
+            ),
+        };
+        let width = width + GAP;
+        let height = height + GAP + TITLE_HEIGHT;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif">"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"  <text x="{GAP}" y="{}" font-size="20" font-weight="bold">Layer {}</text>"#,
+            TITLE_HEIGHT - 12,
+            layer + 1
+        );
+
+        for (row_idx, row) in macropad.layers[layer].buttons.iter().enumerate() {
+            for (col_idx, btn) in row.iter().enumerate() {
+                let x = GAP + grid_origin.0 + col_idx as u32 * (KEY_WIDTH + GAP);
+                let y = TITLE_HEIGHT + grid_origin.1 + row_idx as u32 * (KEY_HEIGHT + GAP);
+                Self::svg_button(&mut svg, x, y, btn);
+            }
+        }
+
+        for (idx, knob) in macropad.layers[layer].knobs.iter().enumerate() {
+            let x = GAP + knob_origin.0 + idx as u32 * knob_step.0;
+            let y = TITLE_HEIGHT + knob_origin.1 + idx as u32 * knob_step.1;
+            Self::svg_knob(&mut svg, x, y, knob);
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Returns a HTML page with the diagrams of every layer, ready to be printed
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be rendered
+    ///
+    pub fn html(macropad: &Macropad) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>Macropad cheat sheet</title>\n");
+        html.push_str(
+            "<style>\nbody { font-family: sans-serif; }\n\
+             .layer { margin-bottom: 2em; page-break-inside: avoid; }\n</style>\n",
+        );
+        html.push_str("</head>\n<body>\n");
+        for i in 0..macropad.layers.len() {
+            html.push_str("<div class=\"layer\">\n");
+            html.push_str(&Self::svg(macropad, i));
+            html.push_str("</div>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    fn svg_button(svg: &mut String, x: u32, y: u32, btn: &Button) {
+        let _ = writeln!(
+            svg,
+            r##"  <rect x="{x}" y="{y}" width="{KEY_WIDTH}" height="{KEY_HEIGHT}" rx="8" fill="#f4f4f4" stroke="#333"/>"##
+        );
+        let (title, subtitle) = Self::button_text(btn);
+        let mut lines = Self::wrap(&title, 2);
+        let title_lines = lines.len();
+        lines.extend(Self::wrap(&subtitle, 3 - title_lines));
+        let center = x + KEY_WIDTH / 2;
+        let mut text_y = y + KEY_HEIGHT / 2 + 5 - (lines.len().saturating_sub(1) as u32) * 8;
+        for (i, line) in lines.iter().enumerate() {
+            let (size, color) = if i < title_lines {
+                (13, "#000")
+            } else {
+                (10, "#555")
+            };
+            let _ = writeln!(
+                svg,
+                r#"  <text x="{center}" y="{text_y}" font-size="{size}" fill="{color}" text-anchor="middle">{}</text>"#,
+                Self::escape(line)
+            );
+            text_y += 16;
+        }
+    }
+
+    fn svg_knob(svg: &mut String, x: u32, y: u32, knob: &Knob) {
+        let _ = writeln!(
+            svg,
+            r##"  <rect x="{x}" y="{y}" width="{KNOB_WIDTH}" height="{KNOB_HEIGHT}" rx="8" fill="#fff" stroke="#333"/>"##
+        );
+        let _ = writeln!(
+            svg,
+            r##"  <circle cx="{}" cy="{}" r="14" fill="#ddd" stroke="#333"/>"##,
+            x + 22,
+            y + KNOB_HEIGHT / 2
+        );
+        let mut text_y = y + 30;
+        for (symbol, btn) in [("↺", &knob.ccw), ("●", &knob.press), ("↻", &knob.cw)] {
+            let (title, _) = Self::button_text(btn);
+            let line = Self::wrap(&title, 1).pop().unwrap_or_default();
+            let _ = writeln!(
+                svg,
+                r#"  <text x="{}" y="{text_y}" font-size="12">{symbol} {}</text>"#,
+                x + 44,
+                Self::escape(&line)
+            );
+            text_y += 28;
+        }
+    }
+
+    /// Returns the main text and the secondary text for a button. The label is
+    /// preferred as main text, in which case the mapping becomes secondary
+    fn button_text(btn: &Button) -> (String, String) {
+        let mapping = Mapping::pretty(&btn.mapping);
+        if btn.label.is_empty() {
+            (mapping, String::new())
+        } else {
+            (btn.label.clone(), mapping)
+        }
+    }
+
+    /// Splits text into at most `max_lines` lines, shortening the last one if needed
+    fn wrap(text: &str, max_lines: usize) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        if max_lines == 0 {
+            return lines;
+        }
+        let mut current = String::new();
+        for word in text.split_whitespace() {
+            if !current.is_empty() && current.chars().count() + word.chars().count() >= LINE_LEN {
+                lines.push(std::mem::take(&mut current));
+            } else if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        if !current.is_empty() {
+            lines.push(current);
+        }
+
+        if lines.len() > max_lines {
+            lines.truncate(max_lines);
+            lines[max_lines - 1].push('…');
+        }
+        for line in lines.iter_mut() {
+            if line.chars().count() > LINE_LEN + 1 {
+                *line = line.chars().take(LINE_LEN).collect::<String>() + "…";
+            }
+        }
+        lines
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Orientation;
+    use crate::mapping::Macropad;
+    use crate::sheet::Sheet;
+
+    fn macropad() -> Macropad {
+        let mut mp = Macropad::new(2, 3, 1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-shift-v".to_string();
+        mp.layers[0].buttons[1][2].mapping = "ctrl-a,ctrl-s".to_string();
+        mp.layers[0].buttons[1][2].label = "Save <all>".to_string();
+        mp.layers[0].knobs[0].cw.mapping = "volumeup".to_string();
+        mp
+    }
+
+    #[test]
+    fn svg_layer() {
+        let svg = Sheet::svg(&macropad(), 0);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(">Layer 1<"));
+        assert!(svg.contains(">Ctrl+Shift+V<"));
+        assert!(svg.contains(">Save &lt;all&gt;<"));
+        assert!(svg.contains(">Ctrl+A, Ctrl+S<"));
+        assert!(svg.contains("↻ VolumeUp"));
+        assert_eq!(svg.matches("<rect").count(), 7);
+    }
+
+    #[test]
+    fn svg_orientation() {
+        let mut mp = macropad();
+        // knob on the right of the buttons
+        let svg = Sheet::svg(&mp, 0);
+        assert!(svg.contains(r#"<rect x="400" y="40" width="170""#));
+
+        // knob on the left of the buttons
+        mp.device.orientation = Orientation::UpsideDown;
+        let svg = Sheet::svg(&mp, 0);
+        assert!(svg.contains(r#"<rect x="10" y="40" width="170""#));
+
+        // knob below the buttons
+        mp.device.orientation = Orientation::Clockwise;
+        let svg = Sheet::svg(&mp, 0);
+        assert!(svg.contains(r#"<rect x="10" y="220" width="170""#));
+    }
+
+    #[test]
+    fn html_all_layers() {
+        let html = Sheet::html(&macropad());
+        assert_eq!(html.matches("<svg").count(), 3);
+        assert!(html.contains(">Layer 3<"));
+    }
+
+    #[test]
+    fn wrap_text() {
+        assert_eq!(Sheet::wrap("Ctrl+A, Ctrl+S", 2), vec!["Ctrl+A, Ctrl+S"]);
+        assert_eq!(
+            Sheet::wrap("F, O, O, Shift+2, B, A, R, Dot, C, O, M", 2),
+            vec!["F, O, O,", "Shift+2, B, A,…"]
+        );
+        assert!(Sheet::wrap("anything", 0).is_empty());
+    }
+}
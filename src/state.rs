@@ -0,0 +1,102 @@
+use crate::mapping::{Macropad, Mapping};
+use crate::profile::DeviceProfile;
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::path::PathBuf;
+
+/// Local state of the tool. Keeps a shadow copy of the last configuration
+/// programmed into each device, so it can be shown for devices that cannot
+/// be read back
+pub struct State {
+    dir: PathBuf,
+}
+
+impl State {
+    /// Returns the state kept in the default state directory
+    ///
+    pub fn open() -> Result<Self> {
+        let dir = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .ok_or_else(|| anyhow!("unable to find a directory to keep local state"))?;
+        Ok(Self::new(dir.join("macropad-tool")))
+    }
+
+    /// Returns the state kept in the specified directory
+    ///
+    /// #Arguments
+    /// `dir` - directory holding the state
+    ///
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Stores the configuration that was programmed into the device
+    ///
+    /// #Arguments
+    /// `profile` - profile of the device that was programmed
+    /// `macropad` - configuration that was programmed
+    ///
+    pub fn save(&self, profile: &DeviceProfile, macropad: &Macropad) -> Result<()> {
+        let file = self.shadow_file(profile);
+        debug!("saving shadow copy to {}", file.display());
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating {}", self.dir.display()))?;
+        Mapping::write(macropad, &file.to_string_lossy())
+            .with_context(|| format!("writing {}", file.display()))
+    }
+
+    /// Returns the configuration last programmed into the device, if there is one
+    ///
+    /// #Arguments
+    /// `profile` - profile of the device
+    ///
+    pub fn load(&self, profile: &DeviceProfile) -> Result<Option<Macropad>> {
+        let file = self.shadow_file(profile);
+        debug!("loading shadow copy from {}", file.display());
+        if !file.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read_to_string(&file)
+            .with_context(|| format!("reading {}", file.display()))?;
+        let macropad =
+            Mapping::parse(&data).with_context(|| format!("parsing {}", file.display()))?;
+        Ok(Some(macropad))
+    }
+
+    /// The devices have no serial number, so they are identified by vendor/product id
+    fn shadow_file(&self, profile: &DeviceProfile) -> PathBuf {
+        self.dir.join(format!(
+            "{:04x}-{:04x}.ron",
+            profile.vendor_id, profile.product_id
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::VENDOR_ID;
+    use crate::mapping::Macropad;
+    use crate::profile::Profiles;
+    use crate::state::State;
+
+    #[test]
+    fn shadow_copy() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("macropad-tool-state-{}", std::process::id()));
+        let state = State::new(dir.clone());
+        let profiles = Profiles::builtin();
+        let p8890 = profiles.get(VENDOR_ID, 0x8890)?;
+        let p8840 = profiles.get(VENDOR_ID, 0x8840)?;
+        assert!(state.load(p8890)?.is_none());
+
+        let mut mp = Macropad::new(1, 3, 1);
+        mp.layers[0].buttons[0][2].mapping = "ctrl-c".to_string();
+        state.save(p8890, &mp)?;
+        let shadow = state.load(p8890)?.expect("shadow copy");
+        assert_eq!(shadow.layers[0].buttons[0][2].mapping, "ctrl-c");
+        assert!(state.load(p8840)?.is_none());
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use crate::profile::Profiles;
+
+use anyhow::{ensure, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Default directory udev rules are installed to
+pub const RULES_DIR: &str = "/etc/udev/rules.d";
+
+/// How access to the devices is granted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// device node mode and group
+    Mode { mode: String, group: String },
+    /// access for the user logged in at the seat (systemd-logind)
+    Uaccess,
+}
+
+/// udev rules giving access to the devices of the device profiles
+pub struct UdevRules {}
+
+impl UdevRules {
+    /// Returns the name of the rules file. uaccess rules must be applied
+    /// before systemd's 73-seat-late.rules
+    ///
+    /// #Arguments
+    /// `access` - how access is granted
+    ///
+    pub fn file_name(access: &Access) -> &'static str {
+        match access {
+            Access::Mode { .. } => "80-macropad.rules",
+            Access::Uaccess => "70-macropad.rules",
+        }
+    }
+
+    /// Returns the rules for the devices of the profiles
+    ///
+    /// #Arguments
+    /// `profiles` - device profiles
+    /// `access` - how access is granted
+    ///
+    pub fn generate(profiles: &Profiles, access: &Access) -> Result<String> {
+        let permission = match access {
+            Access::Mode { mode, group } => {
+                ensure!(
+                    (3..=4).contains(&mode.len()) && mode.chars().all(|c| ('0'..='7').contains(&c)),
+                    "mode {mode} is not an octal file mode"
+                );
+                ensure!(
+                    !group.is_empty()
+                        && group
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c)),
+                    "group {group:?} is not a valid group name"
+                );
+                format!("MODE=\"{mode}\", GROUP=\"{group}\"")
+            }
+            Access::Uaccess => "TAG+=\"uaccess\"".to_string(),
+        };
+
+        let mut devices: Vec<_> = profiles
+            .iter()
+            .map(|p| (p.vendor_id, p.product_id, &p.name))
+            .collect();
+        devices.sort();
+        let mut rules = String::from("# generated by macropad-tool udev-rules\n");
+        for (vid, pid, name) in devices {
+            // names come from profile files, they must not start another line
+            let name: String = name
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect();
+            rules += &format!(
+                "# {name}\nATTRS{{idProduct}}==\"{pid:04x}\", ATTRS{{idVendor}}==\"{vid:04x}\", {permission}\n"
+            );
+        }
+        Ok(rules)
+    }
+
+    /// Writes the rules to the rules directory and returns the file written
+    ///
+    /// #Arguments
+    /// `rules` - generated rules
+    /// `dir` - rules directory
+    /// `access` - how access is granted, names the file
+    ///
+    pub fn install(rules: &str, dir: &Path, access: &Access) -> Result<PathBuf> {
+        ensure!(dir.is_dir(), "{} is not a directory", dir.display());
+        let file = dir.join(Self::file_name(access));
+        std::fs::write(&file, rules).with_context(|| format!("writing {}", file.display()))?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::VENDOR_ID;
+    use crate::profile::Profiles;
+    use crate::udev::{Access, UdevRules};
+
+    fn default_access() -> Access {
+        Access::Mode {
+            mode: "666".to_string(),
+            group: "users".to_string(),
+        }
+    }
+
+    #[test]
+    fn shipped_rules_are_generated() -> anyhow::Result<()> {
+        let rules = UdevRules::generate(&Profiles::builtin(), &default_access())?;
+        assert_eq!(rules, include_str!("../80-macropad.rules"));
+        Ok(())
+    }
+
+    #[test]
+    fn access() -> anyhow::Result<()> {
+        let rules = UdevRules::generate(&Profiles::builtin(), &Access::Uaccess)?;
+        assert!(rules
+            .contains("ATTRS{idProduct}==\"8890\", ATTRS{idVendor}==\"1189\", TAG+=\"uaccess\"\n"));
+        assert_eq!(UdevRules::file_name(&Access::Uaccess), "70-macropad.rules");
+
+        let bad_mode = Access::Mode {
+            mode: "rw".to_string(),
+            group: "users".to_string(),
+        };
+        assert!(UdevRules::generate(&Profiles::builtin(), &bad_mode).is_err());
+        let bad_group = Access::Mode {
+            mode: "660".to_string(),
+            group: "us\"ers".to_string(),
+        };
+        assert!(UdevRules::generate(&Profiles::builtin(), &bad_group).is_err());
+
+        // profile names stay on their comment line
+        let mut profiles = Profiles::builtin();
+        let mut profile = profiles.get(VENDOR_ID, 0x8890)?.clone();
+        profile.product_id = 0x1234;
+        profile.name = "pad\nKERNEL==\"*\", MODE=\"0666\"\r".to_string();
+        profiles.add(profile);
+        let rules = UdevRules::generate(&profiles, &Access::Uaccess)?;
+        assert!(rules.contains("# pad KERNEL==\"*\", MODE=\"0666\" \n"));
+        assert!(!rules.lines().any(|l| l.starts_with("KERNEL")));
+
+        let dir = std::env::temp_dir().join(format!("macropad-tool-udev-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let file = UdevRules::install("rules\n", &dir, &default_access())?;
+        assert_eq!(std::fs::read_to_string(&file)?, "rules\n");
+        assert!(file.ends_with("80-macropad.rules"));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use crate::config::Orientation;
+use crate::keyboard::Configuration;
+use crate::mapping::{Button, Knob, Macropad, Mapping};
+
+use anyhow::Result;
+
+/// How a Macropad is displayed in the terminal
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ViewFormat {
+    /// Configuration file format
+    #[default]
+    Ron,
+    /// Box drawing of the buttons and knobs of each layer
+    Grid,
+}
+
+/// Number of characters inside a button cell
+const CELL_WIDTH: usize = 14;
+
+pub struct View {}
+
+impl View {
+    /// Prints the Macropad to stdout as a grid for every layer
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be displayed
+    /// `numbers` - when set, overlays the key numbers used to program the device
+    ///
+    pub fn print_grid(macropad: &Macropad, numbers: Option<&dyn Configuration>) -> Result<()> {
+        for i in 0..macropad.layers.len() {
+            println!("{}", Self::grid(macropad, i, numbers)?);
+        }
+        Ok(())
+    }
+
+    /// Returns the grid of buttons and knobs of a layer with the orientation applied
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be displayed
+    /// `layer` - layer to display (zero based)
+    /// `numbers` - when set, overlays the key numbers used to program the device
+    ///
+    pub fn grid(
+        macropad: &Macropad,
+        layer: usize,
+        numbers: Option<&dyn Configuration>,
+    ) -> Result<String> {
+        let layout = match numbers {
+            Some(cfg) => Some(cfg.device_layout(&macropad.device)?),
+            None => None,
+        };
+
+        let buttons = Self::buttons(&macropad.layers[layer].buttons, layout.as_ref());
+        let knobs: Vec<Vec<String>> = macropad.layers[layer]
+            .knobs
+            .iter()
+            .enumerate()
+            .map(|(idx, knob)| {
+                Self::knob(
+                    idx,
+                    knob,
+                    numbers.map(|cfg| cfg.knob_base() + 3 * idx as u8),
+                )
+            })
+            .collect();
+
+        // knobs go on the side of the buttons for horizontal orientations and
+        // on top/bottom of them for vertical ones
+        let lines = match macropad.device.orientation {
+            Orientation::Normal => Self::beside(&[buttons, Self::beside(&knobs)]),
+            Orientation::UpsideDown => Self::beside(&[Self::beside(&knobs), buttons]),
+            Orientation::Clockwise => [buttons, knobs.concat()].concat(),
+            Orientation::CounterClockwise => [knobs.concat(), buttons].concat(),
+        };
+
+        let mut out = format!("Layer {}\n", layer + 1);
+        for line in lines {
+            out += line.trim_end();
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    fn buttons(buttons: &[Vec<Button>], layout: Option<&Vec<Vec<u8>>>) -> Vec<String> {
+        let cols = buttons.first().map(|r| r.len()).unwrap_or(0);
+        let border = |left: &str, mid: &str, right: &str| {
+            format!(
+                "{left}{}{right}",
+                vec!["─".repeat(CELL_WIDTH + 2); cols].join(mid)
+            )
+        };
+
+        let mut lines = vec![border("┌", "┬", "┐")];
+        for (row_idx, row) in buttons.iter().enumerate() {
+            if row_idx > 0 {
+                lines.push(border("├", "┼", "┤"));
+            }
+            let mut title = String::from("│");
+            let mut detail = String::from("│");
+            for (col_idx, btn) in row.iter().enumerate() {
+                let (main, sub) = Self::button_text(btn);
+                // buttons outside the layout of the device section have no key number
+                let key_num = layout.and_then(|l| l.get(row_idx).and_then(|r| r.get(col_idx)));
+                let sub = match key_num {
+                    Some(n) => format!("{:>width$}", format!("#0x{n:02x}"), width = CELL_WIDTH),
+                    None => sub,
+                };
+                title += &format!(" {} │", Self::fit(&main));
+                detail += &format!(" {} │", Self::fit(&sub));
+            }
+            lines.push(title);
+            lines.push(detail);
+        }
+        lines.push(border("└", "┴", "┘"));
+        lines
+    }
+
+    fn knob(idx: usize, knob: &Knob, number: Option<u8>) -> Vec<String> {
+        let width = CELL_WIDTH + 4;
+        let mut lines = vec![format!("╭{:─<width$}╮", format!(" knob {} ", idx + 1))];
+        for (i, (symbol, btn)) in [("↺", &knob.ccw), ("●", &knob.press), ("↻", &knob.cw)]
+            .into_iter()
+            .enumerate()
+        {
+            let (main, _) = Self::button_text(btn);
+            let text = match number {
+                Some(n) => format!(
+                    "{symbol} {} #0x{:02x}",
+                    Self::fit_to(&main, width - 10),
+                    n + i as u8
+                ),
+                None => format!("{symbol} {}", Self::fit_to(&main, width - 4)),
+            };
+            lines.push(format!("│ {} │", Self::fit_to(&text, width - 2)));
+        }
+        lines.push(format!("╰{}╯", "─".repeat(width)));
+        lines
+    }
+
+    /// Returns the text to show in a button: the label (if any) and the mapping
+    fn button_text(btn: &Button) -> (String, String) {
+        let mapping = Mapping::pretty(&btn.mapping);
+        if btn.label.is_empty() {
+            (mapping, String::new())
+        } else {
+            (btn.label.clone(), mapping)
+        }
+    }
+
+    fn fit(text: &str) -> String {
+        Self::fit_to(text, CELL_WIDTH)
+    }
+
+    /// Pads or shortens `text` to exactly `width` characters
+    fn fit_to(text: &str, width: usize) -> String {
+        if text.chars().count() > width {
+            text.chars().take(width - 1).collect::<String>() + "…"
+        } else {
+            format!("{text:<width$}")
+        }
+    }
+
+    /// Places blocks of lines next to each other, top aligned
+    fn beside(blocks: &[Vec<String>]) -> Vec<String> {
+        let height = blocks.iter().map(|b| b.len()).max().unwrap_or(0);
+        (0..height)
+            .map(|i| {
+                blocks
+                    .iter()
+                    .filter(|b| !b.is_empty())
+                    .map(|b| {
+                        let width = b.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+                        let line = b.get(i).map(String::as_str).unwrap_or("");
+                        format!("{line:<width$}")
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Orientation;
+    use crate::consts::VENDOR_ID;
+    use crate::keyboard::{k884x::Keyboard884x, k8890::Keyboard8890};
+    use crate::mapping::Macropad;
+    use crate::profile::Profiles;
+    use crate::view::View;
+
+    fn macropad() -> Macropad {
+        let mut mp = Macropad::new(2, 3, 1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-shift-v".to_string();
+        mp.layers[0].buttons[1][2].mapping = "ctrl-a,ctrl-s".to_string();
+        mp.layers[0].buttons[1][2].label = "Save".to_string();
+        mp.layers[0].knobs[0].cw.mapping = "volumeup".to_string();
+        mp
+    }
+
+    #[test]
+    fn grid_normal() -> anyhow::Result<()> {
+        let grid = View::grid(&macropad(), 0, None)?;
+        let lines: Vec<_> = grid.lines().collect();
+        assert_eq!(lines[0], "Layer 1");
+        assert!(lines[1].starts_with("┌────"));
+        assert!(lines[1].ends_with("╭ knob 1 ──────────╮"));
+        assert!(lines[2].starts_with("│ Ctrl+Shift+V   │"));
+        assert!(lines[5].contains("│ Save           │"));
+        assert!(lines[6].contains("│ Ctrl+A, Ctrl+S │"));
+        assert!(lines[4].ends_with("│ ↻ VolumeUp       │"));
+        Ok(())
+    }
+
+    #[test]
+    fn grid_orientation() -> anyhow::Result<()> {
+        let mut mp = macropad();
+        mp.device.orientation = Orientation::UpsideDown;
+        let grid = View::grid(&mp, 0, None)?;
+        assert!(grid.lines().nth(1).unwrap().starts_with("╭ knob 1"));
+
+        mp.device.orientation = Orientation::Clockwise;
+        let grid = View::grid(&mp, 0, None)?;
+        assert!(grid.lines().nth(1).unwrap().starts_with("┌"));
+        assert!(grid.lines().nth(8).unwrap().starts_with("╭ knob 1"));
+
+        mp.device.orientation = Orientation::CounterClockwise;
+        let grid = View::grid(&mp, 0, None)?;
+        assert!(grid.lines().nth(1).unwrap().starts_with("╭ knob 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn grid_key_numbers() -> anyhow::Result<()> {
+        let mut mp = macropad();
+        let profiles = Profiles::builtin();
+        let kbd = Keyboard884x::new(None, profiles.get(VENDOR_ID, 0x8840)?.clone())?;
+        let grid = View::grid(&mp, 0, Some(&kbd))?;
+        assert!(grid
+            .lines()
+            .nth(3)
+            .unwrap()
+            .starts_with("│          #0x01 │"));
+        assert!(grid.contains("#0x10"));
+        assert!(grid.contains("#0x12"));
+
+        mp.device.orientation = Orientation::UpsideDown;
+        let kbd = Keyboard8890::new(None, profiles.get(VENDOR_ID, 0x8890)?.clone())?;
+        let grid = View::grid(&mp, 0, Some(&kbd))?;
+        assert!(grid.contains("│          #0x06 │"));
+        assert!(grid.contains("#0x0d"));
+
+        // rows longer than the device section have no key numbers
+        let mut mp = macropad();
+        (mp.device.rows, mp.device.cols) = (1, 2);
+        let kbd = Keyboard884x::new(None, profiles.get(VENDOR_ID, 0x8840)?.clone())?;
+        let grid = View::grid(&mp, 0, Some(&kbd))?;
+        assert!(grid.contains("#0x02"));
+        assert!(!grid.contains("#0x03"));
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use crate::consts;
+use crate::keyboard::transport;
+
+use anyhow::{ensure, Context as _, Result};
+use itertools::Itertools as _;
+use log::{debug, info};
+use rusb::{Context, Device, DeviceDescriptor, Hotplug, HotplugBuilder, UsbContext as _};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Forwards the devices that arrive to the watch loop. Devices cannot be
+/// opened in the hotplug callback itself
+struct Arrivals {
+    /// bus and address of the arrived devices
+    tx: Sender<(u8, u8)>,
+}
+
+impl Hotplug<Context> for Arrivals {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        let _ = self.tx.send((device.bus_number(), device.address()));
+    }
+
+    fn device_left(&mut self, device: Device<Context>) {
+        info!("{} left", Watch::port_path(&device));
+    }
+}
+
+/// Waits for devices to be plugged in and programs them
+pub struct Watch {
+    /// vendor id of the devices to watch for
+    vendor_id: u16,
+    /// only program devices plugged into this port (eg. 1-2.3)
+    port: Option<String>,
+    /// time during which a device arriving again on the same port is not programmed again
+    debounce: Duration,
+}
+
+impl Watch {
+    /// Returns the watch for devices of the vendor
+    ///
+    /// #Arguments
+    /// `vendor_id` - vendor id of the devices to watch for
+    /// `port` - Optional port path the device must be plugged into (eg. 1-2.3)
+    /// `debounce` - time during which a device arriving again on the same port is ignored
+    ///
+    pub fn new(vendor_id: u16, port: Option<String>, debounce: Duration) -> Self {
+        Self {
+            vendor_id,
+            port,
+            debounce,
+        }
+    }
+
+    /// Returns the port path of the device, bus number and the ports up to the
+    /// device (eg. 1-2.3), as used by linux in sysfs
+    ///
+    /// #Arguments
+    /// `device` - usb device
+    ///
+    pub fn port_path(device: &Device<Context>) -> String {
+        Self::format_port_path(
+            device.bus_number(),
+            &device.port_numbers().unwrap_or_default(),
+        )
+    }
+
+    fn format_port_path(bus: u8, ports: &[u8]) -> String {
+        if ports.is_empty() {
+            format!("{bus}")
+        } else {
+            format!("{bus}-{}", ports.iter().join("."))
+        }
+    }
+
+    /// Programs the matching devices that are connected or plugged in, until the
+    /// process is stopped or interrupted with Ctrl-C
+    ///
+    /// #Arguments
+    /// `matches` - returns true for the product ids to program
+    /// `program` - programs the device
+    ///
+    pub fn run(
+        &self,
+        matches: impl Fn(u16) -> bool,
+        mut program: impl FnMut(Device<Context>, DeviceDescriptor) -> Result<()>,
+    ) -> Result<()> {
+        let options = vec![
+            #[cfg(windows)]
+            rusb::UsbOption::use_usbdk(),
+        ];
+        let context = Context::with_options(&options).context("initializing libusb")?;
+        ensure!(
+            rusb::has_hotplug(),
+            "libusb does not support hotplug on this platform"
+        );
+        let (tx, rx) = mpsc::channel();
+        // devices already connected are reported as arrived too
+        let _registration = HotplugBuilder::new()
+            .vendor_id(self.vendor_id)
+            .enumerate(true)
+            .register(&context, Box::new(Arrivals { tx }))
+            .context("registering for hotplug events")?;
+        let events = context.clone();
+        thread::spawn(move || loop {
+            if let Err(e) = events.handle_events(None) {
+                eprintln!("Error - handling USB events - {e}");
+                break;
+            }
+        });
+
+        println!(
+            "watching for devices of vendor 0x{:04x}{}, press Ctrl-C to stop",
+            self.vendor_id,
+            self.port
+                .as_ref()
+                .map(|p| format!(" on port {p}"))
+                .unwrap_or_default()
+        );
+        let mut programmed: HashMap<String, Instant> = HashMap::new();
+        for (bus, address) in rx {
+            // give the device time to settle, it may enumerate again
+            thread::sleep(consts::RECLAIM_DELAY);
+            let Some(device) = context
+                .devices()?
+                .iter()
+                .find(|d| d.bus_number() == bus && d.address() == address)
+            else {
+                debug!("device {bus:03}:{address:03} went away");
+                continue;
+            };
+            let desc = device.device_descriptor()?;
+            let path = Self::port_path(&device);
+            if !matches(desc.product_id()) {
+                debug!("{path}: ignoring 0x{:04x}", desc.product_id());
+                continue;
+            }
+            if self.port.as_ref().is_some_and(|port| *port != path) {
+                debug!("{path}: ignoring device on another port");
+                continue;
+            }
+            if programmed
+                .get(&path)
+                .is_some_and(|t| t.elapsed() < self.debounce)
+            {
+                info!("{path}: ignoring device enumerating again");
+                continue;
+            }
+
+            println!(
+                "{path}: 0x{:04x} plugged in, programming",
+                desc.product_id()
+            );
+            // failures are retried as soon as the device is plugged in again
+            match program(device, desc) {
+                Ok(()) => {
+                    println!("{path}: successfully programmed device");
+                    programmed.insert(path, Instant::now());
+                }
+                Err(e) => eprintln!("{path}: programming failed - {e:#}"),
+            }
+            // Ctrl-C while programming only stopped that device, stop watching too
+            if transport::interrupted() {
+                eprintln!("interrupted - stopped watching");
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::watch::Watch;
+
+    #[test]
+    fn port_path() {
+        assert_eq!(Watch::format_port_path(1, &[2, 3]), "1-2.3");
+        assert_eq!(Watch::format_port_path(3, &[4]), "3-4");
+        assert_eq!(Watch::format_port_path(2, &[]), "2");
+    }
+}